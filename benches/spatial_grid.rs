@@ -0,0 +1,116 @@
+//! Headless stress test for a crowded run: thousands of mobs spawned through `SpawnMob`,
+//! chasing the scripted player with the game's own movement, targeting, weapons and physics.
+//! Mobs the player kills are replaced every tick to keep the crowd at the same size, and the
+//! player is healed every tick so the run never ends.
+//!
+//! Run with `cargo bench --bench spatial_grid [-- <mob count>]`.
+
+use rust_survival::prelude::*;
+
+use std::time::{Duration, Instant};
+
+const DEFAULT_MOBS: u32 = 5_000;
+const MOB_KIND: &str = "blob";
+const SEED: u64 = 1;
+/// Frames allowed for loading and spawning the crowd before giving up
+const SETUP_FRAMES: usize = 10_000;
+const WARMUP_FRAMES: usize = 30;
+const MEASURED_FRAMES: usize = 600;
+/// Frame budget for a 60 fps game
+const FRAME_BUDGET: Duration = Duration::from_micros(16_667);
+
+#[derive(Resource)]
+struct CrowdSize(u32);
+
+/// Tops the crowd back up to its size, before this tick's spawns are handled
+fn replace_mobs(
+    crowd: Res<CrowdSize>,
+    count: Res<MobCount>,
+    mut spawn_events: EventWriter<SpawnMob>,
+) {
+    for _ in count.0..crowd.0 {
+        spawn_events.send(SpawnMob {
+            kind: MOB_KIND.to_string(),
+            rank: MobRank::Normal,
+            position: None,
+        });
+    }
+}
+
+fn heal_player(mut player_query: Query<&mut Health, With<Player>>) {
+    for mut health in &mut player_query {
+        health.current = health.max;
+    }
+}
+
+fn main() {
+    let mobs = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_MOBS);
+
+    let mut app = headless_app();
+    app.insert_resource(HeadlessOptions {
+        runs: 1,
+        seed: SEED,
+        max_duration: f32::INFINITY,
+        output: None,
+    })
+    .insert_resource(CrowdSize(mobs))
+    .add_plugins(HeadlessPlugin)
+    .add_systems(
+        FixedUpdate,
+        (
+            replace_mobs.after(count_mobs).before(spawn_mobs),
+            heal_player.after(apply_damage),
+        )
+            .in_set(TickSet::Gameplay)
+            .run_if(in_state(AppState::Playing)),
+    );
+    app.finish();
+    app.cleanup();
+
+    let crowded = |app: &App| {
+        *app.world.resource::<State<AppState>>().get() == AppState::Playing
+            && app.world.resource::<MobCount>().0 >= mobs
+    };
+    for _ in 0..SETUP_FRAMES {
+        if crowded(&app) {
+            break;
+        }
+        app.update();
+    }
+    if !crowded(&app) {
+        eprintln!("Never reached {mobs} mobs, are the assets loading?");
+        std::process::exit(1);
+    }
+
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+    }
+
+    let mut frame_times = Vec::with_capacity(MEASURED_FRAMES);
+    for _ in 0..MEASURED_FRAMES {
+        let start = Instant::now();
+        app.update();
+        frame_times.push(start.elapsed());
+    }
+    frame_times.sort_unstable();
+
+    let mean = frame_times.iter().sum::<Duration>() / MEASURED_FRAMES as u32;
+    let p99 = frame_times[MEASURED_FRAMES * 99 / 100];
+    let worst = frame_times[MEASURED_FRAMES - 1];
+    let director = app.world.resource::<WaveDirector>();
+    println!("{mobs} mobs over {MEASURED_FRAMES} frames");
+    println!("  mean {mean:?}, p99 {p99:?}, worst {worst:?}");
+    println!(
+        "  {} mobs alive after {:.1}s of the run",
+        app.world.resource::<MobCount>().0,
+        director.elapsed
+    );
+    if p99 <= FRAME_BUDGET {
+        println!("  within the {FRAME_BUDGET:?} frame budget");
+    } else {
+        println!("  over the {FRAME_BUDGET:?} frame budget");
+    }
+}
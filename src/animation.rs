@@ -1,59 +1,225 @@
 use crate::prelude::*;
 
+use bevy::utils::HashMap;
+use serde::Deserialize;
 use std::time::Duration;
 
 pub struct SpriteAnimationPlugin;
 
-#[derive(Component, Deref, DerefMut)]
-pub struct AnimationTimer(pub Timer);
+/// What a clip does once it reaches its last frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum LoopMode {
+    /// Starts again from the first frame
+    #[default]
+    Loop,
+    /// Stops and goes back to the first frame
+    Once,
+    /// Plays backwards to the first frame, then forwards again
+    PingPong,
+    /// Stops on the last frame
+    HoldLast,
+}
 
+#[derive(Clone, Copy, Debug)]
 pub struct AnimationFrame {
+    /// Seconds the frame is shown for
     pub time: f32,
-    pub index: i32,
+    /// Index into the sprite's texture atlas
+    pub index: usize,
 }
 
-pub struct Animation {
+#[derive(Clone, Debug)]
+pub struct SpriteClip {
     pub frames: Vec<AnimationFrame>,
-    pub current_frame: i32,
+    pub mode: LoopMode,
+}
+
+impl SpriteClip {
+    pub fn new(frames: Vec<AnimationFrame>, mode: LoopMode) -> Self {
+        Self { frames, mode }
+    }
+}
+
+/// Named clips for a texture atlas sprite, playing one at a time
+#[derive(Component, Default)]
+pub struct SpriteAnimator {
+    clips: HashMap<String, SpriteClip>,
+    current: Option<String>,
+    frame: usize,
+    /// Set while a ping-pong clip plays backwards
+    reversed: bool,
+    finished: bool,
+    timer: Timer,
+    /// Sheet the clips came from, picked up again when it is reloaded
+    sheet: Option<AssetId<SpriteSheet>>,
+}
+
+impl SpriteAnimator {
+    /// Animator with every clip of a loaded sprite sheet, following it when it changes
+    pub fn from_sheet(id: AssetId<SpriteSheet>, sheet: &SpriteSheet) -> Self {
+        Self {
+            clips: sheet.clips.clone(),
+            sheet: Some(id),
+            ..default()
+        }
+    }
+
+    /// Plays the clip called `name` from its first frame, even if it is already playing.
+    /// Returns false, leaving the current clip playing, if there is no such clip.
+    pub fn play(&mut self, name: &str) -> bool {
+        let Some(clip) = self.clips.get(name) else {
+            return false;
+        };
+        let time = clip.frames.first().map_or(0.0, |frame| frame.time);
+        self.current = Some(name.to_string());
+        self.frame = 0;
+        self.reversed = false;
+        self.finished = clip.frames.is_empty();
+        self.timer = Timer::from_seconds(time, TimerMode::Once);
+        true
+    }
+
+    /// Swaps in new clips, restarting the current one if it is still there
+    fn replace_clips(&mut self, clips: HashMap<String, SpriteClip>) {
+        self.clips = clips;
+        if let Some(current) = self.current.clone() {
+            if !self.play(&current) {
+                self.current = None;
+            }
+        }
+    }
+
+    fn clip(&self) -> Option<&SpriteClip> {
+        self.clips.get(self.current.as_deref()?)
+    }
+
+    /// Atlas index the sprite should show right now
+    pub fn atlas_index(&self) -> Option<usize> {
+        self.clip()?.frames.get(self.frame).map(|frame| frame.index)
+    }
+
+    /// Moves past the current frame, returning true if that ended the clip
+    fn advance(&mut self) -> bool {
+        let Some(clip) = self.clip() else {
+            return false;
+        };
+        let (mode, count) = (clip.mode, clip.frames.len());
+        let last = count.saturating_sub(1);
+
+        let mut ended = false;
+        match mode {
+            LoopMode::Loop => self.frame = (self.frame + 1) % count.max(1),
+            LoopMode::Once | LoopMode::HoldLast if self.frame >= last => {
+                ended = true;
+                if mode == LoopMode::Once {
+                    self.frame = 0;
+                }
+            }
+            LoopMode::Once | LoopMode::HoldLast => self.frame += 1,
+            LoopMode::PingPong if count <= 1 => {}
+            LoopMode::PingPong => {
+                if self.reversed && self.frame == 0 || !self.reversed && self.frame >= last {
+                    self.reversed = !self.reversed;
+                }
+                self.frame = if self.reversed {
+                    self.frame - 1
+                } else {
+                    self.frame + 1
+                };
+            }
+        }
+
+        self.finished = ended;
+        if !ended {
+            let time = self
+                .clip()
+                .and_then(|clip| clip.frames.get(self.frame))
+                .map_or(0.0, |frame| frame.time);
+            self.timer.set_duration(Duration::from_secs_f32(time));
+            self.timer.reset();
+        }
+        ended
+    }
 }
 
-#[derive(Component)]
-pub struct Animations {
-    pub animations: Vec<Animation>,
-    pub current_animation: i32,
+/// Sent when a `Once` or `HoldLast` clip plays its last frame out
+#[derive(Event)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub clip: String,
 }
 
 impl Plugin for SpriteAnimationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, animate_sprite);
+        app.init_asset::<SpriteSheet>()
+            .init_asset_loader::<AsepriteLoader>()
+            .add_event::<AnimationFinished>()
+            .add_systems(Update, reload_sprite_sheets)
+            .add_systems(
+                Update,
+                (animate_sprite, run_animation_state_machines)
+                    .chain()
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(OnEnter(AppState::Playing), resume_animation_players)
+            .add_systems(OnExit(AppState::Playing), pause_animation_players);
+    }
+}
+
+fn pause_animation_players(mut query: Query<&mut AnimationPlayer>) {
+    for mut player in &mut query {
+        player.pause();
+    }
+}
+
+fn resume_animation_players(mut query: Query<&mut AnimationPlayer>) {
+    for mut player in &mut query {
+        player.resume();
     }
 }
 
 fn animate_sprite(
     time: Res<Time>,
-    mut query: Query<(
-        &mut AnimationTimer,
-        &mut Animations,
-        &mut TextureAtlasSprite,
-    )>,
+    mut finished_events: EventWriter<AnimationFinished>,
+    mut query: Query<(Entity, &mut SpriteAnimator, &mut TextureAtlasSprite)>,
 ) {
-    for (mut timer, mut animations, mut sprite) in &mut query {
-        timer.tick(time.delta());
-        if timer.just_finished() {
-            let current_animation_index = animations.current_animation;
-            if let Some(animation) = animations
-                .animations
-                .get_mut(current_animation_index as usize)
-            {
-                animation.current_frame += 1;
-                if animation.current_frame as usize >= animation.frames.len() {
-                    animation.current_frame = 0;
-                }
-                if let Some(frame) = animation.frames.get(animation.current_frame as usize) {
-                    timer.set_duration(Duration::from_secs_f32(frame.time));
-                    timer.reset();
-                    sprite.index = frame.index as usize;
-                }
+    for (entity, mut animator, mut sprite) in &mut query {
+        if !animator.finished && animator.timer.tick(time.delta()).finished() && animator.advance()
+        {
+            finished_events.send(AnimationFinished {
+                entity,
+                clip: animator.current.clone().unwrap_or_default(),
+            });
+        }
+        if let Some(index) = animator.atlas_index() {
+            if sprite.index != index {
+                sprite.index = index;
+            }
+        }
+    }
+}
+
+/// Hands a changed sprite sheet's atlas and clips to the animators using it, so edits saved
+/// from Aseprite show up without a restart
+fn reload_sprite_sheets(
+    mut sheet_events: EventReader<AssetEvent<SpriteSheet>>,
+    sheets: Res<Assets<SpriteSheet>>,
+    mut query: Query<(&mut SpriteAnimator, &mut Handle<TextureAtlas>)>,
+) {
+    for event in sheet_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        let Some(sheet) = sheets.get(*id) else {
+            continue;
+        };
+        for (mut animator, mut atlas) in &mut query {
+            if animator.sheet != Some(*id) {
+                continue;
+            }
+            animator.replace_clips(sheet.clips.clone());
+            if *atlas != sheet.atlas {
+                *atlas = sheet.atlas.clone();
             }
         }
     }
@@ -0,0 +1,267 @@
+use crate::prelude::*;
+
+use bevy::utils::HashMap;
+use std::time::Duration;
+
+/// What an animation state or overlay plays
+#[derive(Clone)]
+pub enum AnimationMotion {
+    /// Transform clip for the entity's `AnimationPlayer`
+    Transform {
+        clip: Handle<AnimationClip>,
+        repeat: bool,
+    },
+    /// Named clip of the entity's `SpriteAnimator`, looping however the clip does. Sprites
+    /// can't blend, so they switch straight over.
+    Sprite(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationParam {
+    Float(f32),
+    Bool(bool),
+    /// Set for a single update, then cleared whether anything used it or not
+    Trigger,
+}
+
+/// Test on a state machine's parameters, with missing parameters counting as zero or false
+#[derive(Clone, Debug)]
+pub enum AnimationCondition {
+    Greater(String, f32),
+    LessOrEqual(String, f32),
+    True(String),
+    False(String),
+    Triggered(String),
+}
+
+impl AnimationCondition {
+    fn holds(&self, params: &HashMap<String, AnimationParam>) -> bool {
+        let float = |name: &String| match params.get(name) {
+            Some(AnimationParam::Float(value)) => *value,
+            _ => 0.0,
+        };
+        let flag = |name: &String| matches!(params.get(name), Some(AnimationParam::Bool(true)));
+        match self {
+            AnimationCondition::Greater(name, value) => float(name) > *value,
+            AnimationCondition::LessOrEqual(name, value) => float(name) <= *value,
+            AnimationCondition::True(name) => flag(name),
+            AnimationCondition::False(name) => !flag(name),
+            AnimationCondition::Triggered(name) => {
+                matches!(params.get(name), Some(AnimationParam::Trigger))
+            }
+        }
+    }
+}
+
+/// Move between states once every condition holds, checked in the order transitions were added
+pub struct AnimationTransition {
+    /// State the transition leaves, or any state but `to` if `None`
+    pub from: Option<String>,
+    pub to: String,
+    pub conditions: Vec<AnimationCondition>,
+    pub blend: Duration,
+}
+
+impl AnimationTransition {
+    pub fn new(from: Option<&str>, to: &str, blend: f32) -> Self {
+        Self {
+            from: from.map(str::to_string),
+            to: to.to_string(),
+            conditions: Vec::new(),
+            blend: Duration::from_secs_f32(blend),
+        }
+    }
+
+    pub fn when(mut self, condition: AnimationCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+}
+
+/// One-shot motion played over whatever state the machine is in, handing back to it once it ends.
+/// States keep changing underneath while it plays.
+pub struct AnimationOverlay {
+    pub motion: AnimationMotion,
+    pub conditions: Vec<AnimationCondition>,
+    /// Used both going into the overlay and back out of it
+    pub blend: Duration,
+}
+
+impl AnimationOverlay {
+    pub fn new(motion: AnimationMotion, blend: f32) -> Self {
+        Self {
+            motion,
+            conditions: Vec::new(),
+            blend: Duration::from_secs_f32(blend),
+        }
+    }
+
+    pub fn when(mut self, condition: AnimationCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+}
+
+/// Picks what an `AnimationPlayer` or `SpriteAnimator` on the same entity plays from parameters
+/// set by gameplay systems, only touching it when the state changes
+#[derive(Component)]
+pub struct AnimationStateMachine {
+    states: HashMap<String, AnimationMotion>,
+    transitions: Vec<AnimationTransition>,
+    overlays: Vec<AnimationOverlay>,
+    params: HashMap<String, AnimationParam>,
+    current: String,
+    /// Index of the overlay playing, if one is
+    overlay: Option<usize>,
+    /// Set when the current state's motion needs starting, with the blend to start it with
+    restart: Option<Duration>,
+}
+
+impl AnimationStateMachine {
+    pub fn new(state: &str, motion: AnimationMotion) -> Self {
+        Self {
+            states: HashMap::from([(state.to_string(), motion)]),
+            transitions: Vec::new(),
+            overlays: Vec::new(),
+            params: HashMap::new(),
+            current: state.to_string(),
+            overlay: None,
+            restart: Some(Duration::ZERO),
+        }
+    }
+
+    pub fn with_state(mut self, state: &str, motion: AnimationMotion) -> Self {
+        self.states.insert(state.to_string(), motion);
+        self
+    }
+
+    pub fn with_transition(mut self, transition: AnimationTransition) -> Self {
+        self.transitions.push(transition);
+        self
+    }
+
+    pub fn with_overlay(mut self, overlay: AnimationOverlay) -> Self {
+        self.overlays.push(overlay);
+        self
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.params
+            .insert(name.to_string(), AnimationParam::Float(value));
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.params
+            .insert(name.to_string(), AnimationParam::Bool(value));
+    }
+
+    pub fn trigger(&mut self, name: &str) {
+        self.params
+            .insert(name.to_string(), AnimationParam::Trigger);
+    }
+
+    fn overlay_motion(&self) -> Option<&AnimationMotion> {
+        Some(&self.overlays.get(self.overlay?)?.motion)
+    }
+
+    /// Ends the playing overlay, going back to the current state
+    fn finish_overlay(&mut self) {
+        if let Some(overlay) = self
+            .overlay
+            .take()
+            .and_then(|index| self.overlays.get(index))
+        {
+            self.restart = Some(overlay.blend);
+        }
+    }
+
+    /// Takes at most one transition and starts an overlay if one is due, returning the motion
+    /// to start playing, if any, and how long to blend into it
+    fn update(&mut self) -> Option<(AnimationMotion, Duration)> {
+        let params = &self.params;
+        let current = &self.current;
+        if let Some(transition) = self.transitions.iter().find(|transition| {
+            transition.to != *current
+                && !matches!(&transition.from, Some(from) if from != current)
+                && self.states.contains_key(&transition.to)
+                && transition
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.holds(params))
+        }) {
+            self.current = transition.to.clone();
+            self.restart = Some(transition.blend);
+        }
+
+        let mut started = None;
+        if self.overlay.is_none() {
+            self.overlay = self.overlays.iter().position(|overlay| {
+                overlay
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.holds(params))
+            });
+            started = self.overlay;
+        }
+        self.params
+            .retain(|_, param| *param != AnimationParam::Trigger);
+
+        if self.overlay.is_some() {
+            // The state starts again once the overlay is done, whatever happened under it
+            let overlay = &self.overlays[started?];
+            return Some((overlay.motion.clone(), overlay.blend));
+        }
+        let blend = self.restart.take()?;
+        Some((self.states.get(&self.current)?.clone(), blend))
+    }
+}
+
+pub fn run_animation_state_machines(
+    mut finished_events: EventReader<AnimationFinished>,
+    mut query: Query<(
+        Entity,
+        &mut AnimationStateMachine,
+        Option<&mut AnimationPlayer>,
+        Option<&mut SpriteAnimator>,
+    )>,
+) {
+    let finished: Vec<(Entity, String)> = finished_events
+        .read()
+        .map(|event| (event.entity, event.clip.clone()))
+        .collect();
+
+    for (entity, mut machine, mut player, mut animator) in &mut query {
+        let overlay_done = match machine.overlay_motion() {
+            Some(AnimationMotion::Transform { .. }) => match &player {
+                Some(player) => player.is_finished(),
+                None => true,
+            },
+            Some(AnimationMotion::Sprite(clip)) => finished
+                .iter()
+                .any(|(finished, name)| *finished == entity && name == clip),
+            None => false,
+        };
+        if overlay_done {
+            machine.finish_overlay();
+        }
+
+        let Some((motion, blend)) = machine.update() else {
+            continue;
+        };
+        match motion {
+            AnimationMotion::Transform { clip, repeat } => {
+                if let Some(player) = player.as_mut() {
+                    let player = player.play_with_transition(clip, blend);
+                    if repeat {
+                        player.repeat();
+                    }
+                }
+            }
+            AnimationMotion::Sprite(clip) => {
+                if let Some(animator) = animator.as_mut() {
+                    animator.play(&clip);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::prelude::*;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::reflect::TypePath;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::Deserialize;
+use std::path::Path;
+use thiserror::Error;
+
+/// Texture atlas and named clips exported from an Aseprite file
+#[derive(Asset, TypePath)]
+pub struct SpriteSheet {
+    #[dependency]
+    pub atlas: Handle<TextureAtlas>,
+    pub clips: HashMap<String, SpriteClip>,
+}
+
+// Aseprite's JSON export, with frames as an array and only the fields used here
+#[derive(Deserialize)]
+struct AsepriteExport {
+    frames: Vec<AsepriteFrame>,
+    meta: AsepriteMeta,
+}
+
+#[derive(Deserialize)]
+struct AsepriteFrame {
+    frame: AsepriteRect,
+    /// Milliseconds
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct AsepriteRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct AsepriteSize {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AsepriteMeta {
+    /// Sheet image, relative to the JSON file
+    image: String,
+    size: AsepriteSize,
+    #[serde(default)]
+    frame_tags: Vec<AsepriteTag>,
+}
+
+#[derive(Deserialize)]
+struct AsepriteTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: AsepriteDirection,
+    /// Times the tag plays, as a string, left out when it loops forever
+    #[serde(default)]
+    repeat: Option<String>,
+    /// User data set on the tag
+    #[serde(default)]
+    data: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum AsepriteDirection {
+    #[default]
+    Forward,
+    Reverse,
+    Pingpong,
+    PingpongReverse,
+}
+
+impl AsepriteTag {
+    /// Tags with a repeat count play once, holding their last frame if their user data says
+    /// `hold`. The rest loop, or ping-pong.
+    fn loop_mode(&self) -> LoopMode {
+        let repeats = self
+            .repeat
+            .as_deref()
+            .and_then(|repeat| repeat.parse::<u32>().ok())
+            .is_some_and(|repeat| repeat > 0);
+        match (&self.direction, repeats) {
+            (_, true) if self.data.as_deref() == Some("hold") => LoopMode::HoldLast,
+            (_, true) => LoopMode::Once,
+            (AsepriteDirection::Pingpong | AsepriteDirection::PingpongReverse, false) => {
+                LoopMode::PingPong
+            }
+            (AsepriteDirection::Forward | AsepriteDirection::Reverse, false) => LoopMode::Loop,
+        }
+    }
+}
+
+/// Loads the JSON Aseprite exports alongside a sheet image, saved with the `.aseprite.json`
+/// extension and the frames exported as an array. Each tag becomes a clip, a sheet without
+/// tags gets one looping `default` clip of every frame.
+#[derive(Default)]
+pub struct AsepriteLoader;
+
+#[derive(Debug, Error)]
+pub enum AsepriteLoaderError {
+    #[error("could not read sprite sheet: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse sprite sheet, is it exported with frames as an array? {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("tag {tag:?} covers frames {from} to {to}, but the sheet only has {count}")]
+    TagOutOfRange {
+        tag: String,
+        from: usize,
+        to: usize,
+        count: usize,
+    },
+}
+
+impl AssetLoader for AsepriteLoader {
+    type Asset = SpriteSheet;
+    type Settings = ();
+    type Error = AsepriteLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<SpriteSheet, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let export: AsepriteExport = serde_json::from_slice(&bytes)?;
+
+            let image_path = load_context
+                .path()
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(&export.meta.image);
+            let texture: Handle<Image> = load_context.load(image_path);
+            let mut atlas =
+                TextureAtlas::new_empty(texture, Vec2::new(export.meta.size.w, export.meta.size.h));
+            let frames: Vec<AnimationFrame> = export
+                .frames
+                .iter()
+                .map(|frame| {
+                    let rect = &frame.frame;
+                    AnimationFrame {
+                        time: frame.duration as f32 / 1000.0,
+                        index: atlas.add_texture(Rect::new(
+                            rect.x,
+                            rect.y,
+                            rect.x + rect.w,
+                            rect.y + rect.h,
+                        )),
+                    }
+                })
+                .collect();
+
+            let mut clips = HashMap::new();
+            for tag in export.meta.frame_tags.iter() {
+                if tag.from > tag.to || tag.to >= frames.len() {
+                    return Err(AsepriteLoaderError::TagOutOfRange {
+                        tag: tag.name.clone(),
+                        from: tag.from,
+                        to: tag.to,
+                        count: frames.len(),
+                    });
+                }
+                let mut tag_frames = frames[tag.from..=tag.to].to_vec();
+                if matches!(
+                    tag.direction,
+                    AsepriteDirection::Reverse | AsepriteDirection::PingpongReverse
+                ) {
+                    tag_frames.reverse();
+                }
+                clips.insert(
+                    tag.name.clone(),
+                    SpriteClip::new(tag_frames, tag.loop_mode()),
+                );
+            }
+            if clips.is_empty() {
+                clips.insert("default".into(), SpriteClip::new(frames, LoopMode::Loop));
+            }
+
+            Ok(SpriteSheet {
+                atlas: load_context.add_labeled_asset("atlas".into(), atlas),
+                clips,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["aseprite.json"]
+    }
+}
@@ -1,33 +1,277 @@
 use crate::prelude::*;
 
-#[derive(Debug, Resource)]
-pub struct SpriteAssets {
-    pub player_body: Handle<Image>,
-    pub player_eyes: Handle<TextureAtlas>,
+use bevy::asset::io::file::FileAssetReader;
+use bevy::asset::io::Reader;
+use bevy::asset::{
+    AssetLoader, AsyncReadExt, LoadContext, LoadState, RecursiveDependencyLoadState,
+};
+use bevy::render::texture::ImagePlugin;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::marker::PhantomData;
+use thiserror::Error;
+
+/// Folder under the asset root the asset server reads from, which Bevy doesn't expose
+const ASSET_FOLDER: &str = "assets";
+
+/// Resource of asset handles loaded from fixed paths while the app is in `AppState::Loading`
+pub trait AssetCollection: Resource + Sized {
+    fn load(asset_server: &AssetServer) -> Self;
+    fn handles(&self) -> Vec<UntypedHandle>;
+}
+
+/// Declares an `AssetCollection` with a handle field for each `name: Type = "path"` entry.
+/// Register it with `App::load_collection`.
+macro_rules! asset_collection {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $($(#[$field_meta:meta])* pub $field:ident: $asset:ty = $path:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Default, Resource)]
+        pub struct $name {
+            $($(#[$field_meta])* pub $field: Handle<$asset>,)*
+        }
+
+        impl $crate::assets::AssetCollection for $name {
+            fn load(asset_server: &AssetServer) -> Self {
+                Self {
+                    $($field: asset_server.load($path),)*
+                }
+            }
+
+            fn handles(&self) -> Vec<UntypedHandle> {
+                vec![$(self.$field.clone().untyped(),)*]
+            }
+        }
+    };
+}
+pub(crate) use asset_collection;
+
+pub trait LoadCollection {
+    /// Loads the collection at startup and holds the loading screen until it has all loaded
+    fn load_collection<C: AssetCollection>(&mut self) -> &mut Self;
+}
+
+impl LoadCollection for App {
+    fn load_collection<C: AssetCollection>(&mut self) -> &mut Self {
+        self.init_resource::<LoadingAssets>()
+            .add_systems(PreStartup, load_collection::<C>)
+    }
+}
+
+fn load_collection<C: AssetCollection>(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut loading: ResMut<LoadingAssets>,
+) {
+    let collection = C::load(&asset_server);
+    loading.0.extend(collection.handles());
+    commands.insert_resource(collection);
+}
+
+asset_collection! {
+    pub struct SpriteAssets {
+        pub player_body: Image = "player/player-body.png",
+        pub player_eyes: SpriteSheet = "player/player-eyes.aseprite.json",
+        pub circle: Image = "shapes/circle.png",
+        pub square: Image = "shapes/square.png",
+        pub triangle: Image = "shapes/triangle.png",
+        pub diamond: Image = "shapes/diamond.png",
+    }
+}
+
+asset_collection! {
+    pub struct DataAssets {
+        pub weapons: WeaponCatalogue = "data/base.weapons.ron",
+        pub upgrades: UpgradeCatalogue = "data/base.upgrades.ron",
+        pub passives: PassiveCatalogue = "data/base.passives.ron",
+        pub loot: LootTable = "data/base.loot.ron",
+        pub waves: WaveTimeline = "data/base.waves.ron",
+        pub mobs: MobCatalogue = "data/base.mobs.ron",
+    }
+}
+
+/// Handles the loading screen waits on before moving to the main menu
+#[derive(Resource, Default)]
+pub struct LoadingAssets(pub Vec<UntypedHandle>);
+
+#[derive(Clone, Copy, Debug)]
+pub enum AssetFailure {
+    /// There is no file at the path
+    Missing,
+    /// The file is there but its loader gave up on it, logging why
+    Malformed,
+    /// The asset loaded but something it refers to, such as a sheet's image, did not
+    Dependency,
+}
+
+impl std::fmt::Display for AssetFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            AssetFailure::Missing => "missing",
+            AssetFailure::Malformed => "could not be loaded, see the log for why",
+            AssetFailure::Dependency => "something it uses could not be loaded",
+        })
+    }
 }
 
-#[derive(Debug, Resource)]
-pub struct SpriteAtlas {}
+/// How far the loading screen has got through `LoadingAssets`
+#[derive(Resource, Default)]
+pub struct LoadingProgress {
+    pub loaded: usize,
+    pub total: usize,
+    /// Paths that could not be loaded, filled in once everything else has finished
+    pub failures: Vec<(String, AssetFailure)>,
+}
+
+impl LoadingProgress {
+    pub fn fraction(&self) -> f32 {
+        self.loaded as f32 / self.total.max(1) as f32
+    }
+
+    pub fn has_failed(&self) -> bool {
+        !self.failures.is_empty()
+    }
+}
 
 pub struct AssetsPlugin;
 
 impl Plugin for AssetsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreStartup, load_textures);
+        // Without a renderer there is no image loader, so headless runs use placeholder sprites
+        if app.is_plugin_added::<ImagePlugin>() {
+            app.load_collection::<SpriteAssets>();
+        } else {
+            app.init_resource::<SpriteAssets>();
+        }
+        app.init_resource::<LoadingProgress>()
+            .init_asset_loader::<TransformClipLoader>()
+            .load_collection::<DataAssets>()
+            .add_systems(Update, track_loading.run_if(in_state(AppState::Loading)));
     }
 }
 
-fn load_textures(
-    mut commands: Commands,
+/// Counts up loaded assets, moving on to the main menu once all have loaded. If any fail, waits
+/// for the rest to finish so every failure is listed together.
+pub fn track_loading(
     asset_server: Res<AssetServer>,
-    mut atlases: ResMut<Assets<TextureAtlas>>,
+    loading: Res<LoadingAssets>,
+    mut progress: ResMut<LoadingProgress>,
+    mut next_state: ResMut<NextState<AppState>>,
 ) {
-    let player_eyes_handle = asset_server.load("player/player-eyes.png");
-    let player_eyes_atlas =
-        TextureAtlas::from_grid(player_eyes_handle, Vec2::new(256., 256.), 2, 1, None, None);
+    if progress.has_failed() {
+        return;
+    }
+
+    let mut failures = Vec::new();
+    let mut finished = 0;
+    progress.loaded = 0;
+    progress.total = loading.0.len();
+    for handle in loading.0.iter() {
+        let failure = match (
+            asset_server.load_state(handle.id()),
+            asset_server.recursive_dependency_load_state(handle.id()),
+        ) {
+            (LoadState::Loaded, RecursiveDependencyLoadState::Loaded) => {
+                progress.loaded += 1;
+                None
+            }
+            (LoadState::Failed, _) => Some(missing_or_malformed(handle)),
+            (_, RecursiveDependencyLoadState::Failed) => Some(AssetFailure::Dependency),
+            _ => continue,
+        };
+        finished += 1;
+        if let Some(failure) = failure {
+            let path = handle
+                .path()
+                .map_or_else(|| format!("{:?}", handle.id()), |path| path.to_string());
+            failures.push((path, failure));
+        }
+    }
+
+    if finished < progress.total {
+        return;
+    }
+    if failures.is_empty() {
+        next_state.set(AppState::MainMenu);
+        return;
+    }
+    let list: Vec<String> = failures
+        .iter()
+        .map(|(path, failure)| format!("  {path}: {failure}"))
+        .collect();
+    error!(
+        "Could not load {} of {} assets:\n{}",
+        failures.len(),
+        progress.total,
+        list.join("\n")
+    );
+    progress.failures = failures;
+}
 
-    commands.insert_resource(SpriteAssets {
-        player_body: asset_server.load("player/player-body.png"),
-        player_eyes: atlases.add(player_eyes_atlas),
+fn missing_or_malformed(handle: &UntypedHandle) -> AssetFailure {
+    let exists = handle.path().is_some_and(|path| {
+        FileAssetReader::get_base_path()
+            .join(ASSET_FOLDER)
+            .join(path.path())
+            .exists()
     });
+    if exists {
+        AssetFailure::Malformed
+    } else {
+        AssetFailure::Missing
+    }
+}
+
+/// Loads any deserializable asset from a RON file, keyed by a compound extension
+/// such as `weapons.ron` so several data assets can share the `.ron` suffix
+pub struct RonAssetLoader<A> {
+    extensions: &'static [&'static str],
+    _marker: PhantomData<A>,
+}
+
+impl<A> RonAssetLoader<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        Self {
+            extensions,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RonAssetLoaderError {
+    #[error("could not read asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl<A> AssetLoader for RonAssetLoader<A>
+where
+    A: Asset + for<'de> Deserialize<'de>,
+{
+    type Asset = A;
+    type Settings = ();
+    type Error = RonAssetLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<A, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes::<A>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
 }
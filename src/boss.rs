@@ -0,0 +1,385 @@
+use crate::prelude::*;
+
+use rand::Rng;
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+/// Seconds a boss holds off attacking after moving into a new phase
+const PHASE_CHANGE_PAUSE: f32 = 1.5;
+/// Impulse given to the player caught in a slam, away from its centre
+const SLAM_KNOCKBACK: f32 = 600.0;
+/// A boss moving into a new phase pushes the player away if it is closer than this
+const ROAR_RADIUS: f32 = 350.0;
+const ROAR_KNOCKBACK: f32 = 900.0;
+
+/// Part of a boss fight, from a health threshold down to the next phase's
+#[derive(Clone, Deserialize)]
+pub struct BossPhase {
+    /// The phase starts once the boss's health falls to this fraction of its maximum
+    pub health_below: f32,
+    /// Seconds between the end of one attack and the start of the next
+    pub attack_interval: f32,
+    /// Attacks used in turn, looping back to the first
+    pub attacks: Vec<BossAttack>,
+}
+
+#[derive(Clone, Deserialize)]
+pub enum BossAttack {
+    /// Marks `count` circles on and around the player, `spread` apart at most, that go off after
+    /// `telegraph` seconds
+    Slam {
+        count: u32,
+        radius: f32,
+        spread: f32,
+        telegraph: f32,
+        damage: f32,
+    },
+    /// Fires `volleys` rings of `arms` projectiles, each turned `turn` radians from the last
+    Spiral {
+        arms: u32,
+        volleys: u32,
+        interval: f32,
+        turn: f32,
+        speed: f32,
+        damage: f32,
+        telegraph: f32,
+    },
+    /// Calls `count` mobs of `kind` in on a ring around the boss
+    Summon {
+        kind: String,
+        count: u32,
+        radius: f32,
+        telegraph: f32,
+    },
+}
+
+impl BossAttack {
+    /// Seconds of warning before the attack lands
+    pub fn telegraph(&self) -> f32 {
+        match self {
+            BossAttack::Slam { telegraph, .. }
+            | BossAttack::Spiral { telegraph, .. }
+            | BossAttack::Summon { telegraph, .. } => *telegraph,
+        }
+    }
+}
+
+pub enum BossState {
+    /// Waiting before the next attack
+    Cooldown(Timer),
+    /// Winding up the next attack, with a slam's markers already down
+    Telegraph(Timer),
+    /// Firing the volleys of a spiral, `angle` being where the next one starts
+    Spiral {
+        timer: Timer,
+        volleys_left: u32,
+        angle: f32,
+        arms: u32,
+        turn: f32,
+        speed: f32,
+        damage: f32,
+    },
+}
+
+/// Phases and attack cycle of a boss, added to mobs spawned as bosses whose kind has phases
+#[derive(Component)]
+pub struct BossScript {
+    pub phases: Vec<BossPhase>,
+    pub phase: usize,
+    next_attack: usize,
+    pub state: BossState,
+}
+
+impl BossScript {
+    pub fn new(phases: Vec<BossPhase>) -> Self {
+        let interval = phases.first().map_or(0.0, |phase| phase.attack_interval);
+        Self {
+            phases,
+            phase: 0,
+            next_attack: 0,
+            state: BossState::Cooldown(Timer::from_seconds(interval, TimerMode::Once)),
+        }
+    }
+
+    fn current_phase(&self) -> Option<&BossPhase> {
+        self.phases.get(self.phase)
+    }
+
+    fn next_attack(&self) -> Option<&BossAttack> {
+        let attacks = &self.current_phase()?.attacks;
+        attacks.get(self.next_attack % attacks.len().max(1))
+    }
+
+    /// How far through winding up an attack the boss is, if it is
+    pub fn telegraph_progress(&self) -> Option<f32> {
+        match &self.state {
+            BossState::Telegraph(timer) => Some(timer.percent()),
+            _ => None,
+        }
+    }
+
+    fn cool_down(&mut self) {
+        let interval = self
+            .current_phase()
+            .map_or(0.0, |phase| phase.attack_interval);
+        self.state = BossState::Cooldown(Timer::from_seconds(interval, TimerMode::Once));
+    }
+}
+
+/// Circle on the ground that hurts the player standing in it once its timer runs out
+#[derive(Component)]
+pub struct SlamMarker {
+    pub radius: f32,
+    pub damage: f32,
+    pub timer: Timer,
+}
+
+#[derive(Event)]
+pub struct BossPhaseChanged {
+    pub boss: Entity,
+    pub phase: usize,
+}
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BossPhaseChanged>().add_systems(
+            FixedUpdate,
+            (
+                advance_boss_phases.after(apply_damage),
+                roar_on_phase_change
+                    .after(advance_boss_phases)
+                    .before(apply_movement),
+                run_boss_attacks
+                    .after(advance_boss_phases)
+                    .before(spawn_mobs),
+                detonate_slam_markers.before(apply_damage),
+            )
+                .in_set(TickSet::Gameplay)
+                .run_if(in_state(AppState::Playing)),
+        );
+    }
+}
+
+/// Moves bosses on to the lowest phase their health has fallen into, never back up
+pub fn advance_boss_phases(
+    mut phase_events: EventWriter<BossPhaseChanged>,
+    mut boss_query: Query<(Entity, &mut BossScript, &Health)>,
+) {
+    for (entity, mut script, health) in &mut boss_query {
+        let fraction = health.current / health.max.max(f32::EPSILON);
+        let Some(phase) = script
+            .phases
+            .iter()
+            .rposition(|phase| fraction <= phase.health_below)
+        else {
+            continue;
+        };
+        if phase <= script.phase {
+            continue;
+        }
+
+        script.phase = phase;
+        script.next_attack = 0;
+        script.state =
+            BossState::Cooldown(Timer::from_seconds(PHASE_CHANGE_PAUSE, TimerMode::Once));
+        phase_events.send(BossPhaseChanged {
+            boss: entity,
+            phase,
+        });
+    }
+}
+
+/// Shoves the player back from a boss moving into a new phase, giving it room to start over
+pub fn roar_on_phase_change(
+    mut phase_events: EventReader<BossPhaseChanged>,
+    boss_query: Query<(&Transform, &Name), With<Boss>>,
+    mut player_query: Query<(&Transform, &mut MovementController), With<Player>>,
+) {
+    for event in phase_events.read() {
+        let Ok((boss, name)) = boss_query.get(event.boss) else {
+            continue;
+        };
+        info!("{name} entered phase {}", event.phase + 1);
+        let Ok((player, mut controller)) = player_query.get_single_mut() else {
+            continue;
+        };
+        let offset = player.translation.xy() - boss.translation.xy();
+        if offset.length_squared() <= ROAR_RADIUS.powi(2) {
+            controller.apply_impulse(offset.normalize_or_zero() * ROAR_KNOCKBACK);
+        }
+    }
+}
+
+pub fn run_boss_attacks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rng: ResMut<GameRng>,
+    sprites: Res<SpriteAssets>,
+    mut spawn_events: EventWriter<SpawnMob>,
+    player_query: Query<&Transform, With<Player>>,
+    mut boss_query: Query<(&mut BossScript, &Transform, &StatusEffects), Without<Player>>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let target = player.translation.xy();
+
+    for (mut script, transform, effects) in &mut boss_query {
+        // Stuns hold the attack where it is, slam markers already down still go off
+        if !effects.can_act() {
+            continue;
+        }
+        let position = transform.translation.xy();
+        let Some(attack) = script.next_attack().cloned() else {
+            continue;
+        };
+
+        match &mut script.state {
+            BossState::Cooldown(timer) => {
+                if !timer.tick(time.delta()).finished() {
+                    continue;
+                }
+                if let BossAttack::Slam {
+                    count,
+                    radius,
+                    spread,
+                    telegraph,
+                    damage,
+                } = attack
+                {
+                    let rng = rng.stream("bosses");
+                    for i in 0..count {
+                        // The first lands right on the player, the rest scatter around it
+                        let offset = if i == 0 {
+                            Vec2::ZERO
+                        } else {
+                            Vec2::from_angle(rng.gen_range(0.0..TAU))
+                                * spread
+                                * rng.gen_range(0.0f32..1.0).sqrt()
+                        };
+                        commands.spawn((
+                            TransformBundle::from_transform(Transform::from_translation(
+                                (target + offset).extend(0.0),
+                            )),
+                            Name::new("SlamMarker"),
+                            SlamMarker {
+                                radius,
+                                damage,
+                                timer: Timer::from_seconds(telegraph, TimerMode::Once),
+                            },
+                            RunEntity,
+                        ));
+                    }
+                }
+                script.state =
+                    BossState::Telegraph(Timer::from_seconds(attack.telegraph(), TimerMode::Once));
+            }
+            BossState::Telegraph(timer) => {
+                if !timer.tick(time.delta()).finished() {
+                    continue;
+                }
+                script.next_attack += 1;
+                match attack {
+                    BossAttack::Slam { .. } => script.cool_down(),
+                    BossAttack::Spiral {
+                        arms,
+                        volleys,
+                        interval,
+                        turn,
+                        speed,
+                        damage,
+                        ..
+                    } => {
+                        let aim = target - position;
+                        let mut timer = Timer::from_seconds(interval, TimerMode::Repeating);
+                        // Primed so the first volley goes out on the next frame
+                        timer.set_elapsed(timer.duration());
+                        script.state = BossState::Spiral {
+                            timer,
+                            volleys_left: volleys,
+                            angle: aim.y.atan2(aim.x),
+                            arms,
+                            turn,
+                            speed,
+                            damage,
+                        };
+                    }
+                    BossAttack::Summon {
+                        kind,
+                        count,
+                        radius,
+                        ..
+                    } => {
+                        for i in 0..count {
+                            let angle = TAU * i as f32 / count as f32;
+                            spawn_events.send(SpawnMob {
+                                kind: kind.clone(),
+                                rank: MobRank::Normal,
+                                position: Some(position + Vec2::from_angle(angle) * radius),
+                            });
+                        }
+                        script.cool_down();
+                    }
+                }
+            }
+            BossState::Spiral {
+                timer,
+                volleys_left,
+                angle,
+                arms,
+                turn,
+                speed,
+                damage,
+            } => {
+                if !timer.tick(time.delta()).just_finished() {
+                    continue;
+                }
+                for arm in 0..*arms {
+                    let direction = Vec2::from_angle(*angle + TAU * arm as f32 / *arms as f32);
+                    spawn_mob_projectile(
+                        &mut commands,
+                        &sprites,
+                        position,
+                        direction * *speed,
+                        *damage,
+                        None,
+                    );
+                }
+                *angle += *turn;
+                *volleys_left = volleys_left.saturating_sub(1);
+                if *volleys_left == 0 {
+                    script.cool_down();
+                }
+            }
+        }
+    }
+}
+
+pub fn detonate_slam_markers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut marker_query: Query<(Entity, &mut SlamMarker, &Transform)>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+) {
+    let player = player_query.get_single().ok();
+    for (entity, mut marker, transform) in &mut marker_query {
+        if !marker.timer.tick(time.delta()).finished() {
+            continue;
+        }
+        if let Some((player, player_transform)) = player {
+            let offset = player_transform.translation.xy() - transform.translation.xy();
+            if offset.length_squared() <= marker.radius.powi(2) {
+                damage_events.send(DamageEvent {
+                    target: player,
+                    amount: marker.damage,
+                    knockback: offset.normalize_or_zero() * SLAM_KNOCKBACK,
+                    over_time: false,
+                });
+            }
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,108 @@
+use crate::prelude::*;
+
+use bevy::reflect::TypePath;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const CADENCE_CONFIG: &str = "config/default.cadences.ron";
+
+/// Seconds between runs for each named cadence, loaded from a `.cadences.ron` file
+#[derive(Asset, TypePath, Deserialize)]
+#[serde(transparent)]
+pub struct CadenceConfig(pub HashMap<String, f32>);
+
+/// Cadence periods currently in effect, overriding the defaults passed to `fixed_cadence`
+#[derive(Resource, Default)]
+pub struct Cadences {
+    config: Handle<CadenceConfig>,
+    periods: HashMap<String, f32>,
+    /// Bumped at the start of every run so cadence timers start from zero each time
+    epoch: u32,
+}
+
+impl Cadences {
+    pub fn period(&self, name: &str) -> Option<f32> {
+        self.periods.get(name).copied()
+    }
+}
+
+pub struct CadencePlugin;
+
+impl Plugin for CadencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<CadenceConfig>()
+            .register_asset_loader(RonAssetLoader::<CadenceConfig>::new(&["cadences.ron"]))
+            .init_resource::<Cadences>()
+            .add_systems(PreStartup, load_cadence_config)
+            .add_systems(PreUpdate, apply_cadence_config)
+            .add_systems(OnExit(AppState::MainMenu), restart_cadences);
+    }
+}
+
+fn load_cadence_config(mut cadences: ResMut<Cadences>, asset_server: Res<AssetServer>) {
+    cadences.config = asset_server.load(CADENCE_CONFIG);
+}
+
+/// Picks up the config once it loads, and again whenever the file is edited
+pub fn apply_cadence_config(
+    mut events: EventReader<AssetEvent<CadenceConfig>>,
+    configs: Res<Assets<CadenceConfig>>,
+    mut cadences: ResMut<Cadences>,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        if *id != cadences.config.id() {
+            continue;
+        }
+        if let Some(config) = configs.get(*id) {
+            cadences.periods = config.0.clone();
+            info!(
+                "Applied {} cadences from {}",
+                cadences.periods.len(),
+                CADENCE_CONFIG
+            );
+        }
+    }
+}
+
+/// Restarts every cadence timer, so a run doesn't depend on how long was spent in the menus
+pub fn restart_cadences(mut cadences: ResMut<Cadences>) {
+    cadences.epoch = cadences.epoch.wrapping_add(1);
+}
+
+/// Timer behind one use of `fixed_cadence`, restarted along with the run
+#[derive(Default)]
+pub struct CadenceTimer {
+    epoch: u32,
+    timer: Option<Timer>,
+}
+
+/// Run condition that passes once every period configured for `name`, falling back to
+/// `default_period` seconds when the config doesn't list it
+///
+/// Each use keeps its own timer, which keeps ticking while other conditions on the system fail.
+/// Put conditions first with `and_then` to keep it from ticking while they fail.
+pub fn fixed_cadence(
+    name: &'static str,
+    default_period: f32,
+) -> impl FnMut(Local<CadenceTimer>, Res<Time>, Res<Cadences>) -> bool + Clone {
+    move |mut cadence, time, cadences| {
+        let period = Duration::from_secs_f32(cadences.period(name).unwrap_or(default_period));
+        let restarted = cadence.epoch != cadences.epoch;
+        cadence.epoch = cadences.epoch;
+        let timer = cadence
+            .timer
+            .get_or_insert_with(|| Timer::new(period, TimerMode::Repeating));
+        if restarted {
+            timer.reset();
+        }
+        if timer.duration() != period {
+            timer.set_duration(period);
+        }
+        timer.tick(time.delta()).just_finished()
+    }
+}
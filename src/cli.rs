@@ -0,0 +1,58 @@
+use crate::prelude::*;
+
+use std::path::PathBuf;
+
+/// Command line options for both the windowed game and headless runs
+pub struct LaunchOptions {
+    /// Seed every run starts from, picked at random per run when missing
+    pub seed: Option<u64>,
+    /// Set when `--headless` is given
+    pub headless: Option<HeadlessOptions>,
+    /// Replay to play back instead of taking input, checked and exited from when headless
+    pub replay: Option<PathBuf>,
+}
+
+impl LaunchOptions {
+    /// Parses `[--seed N] [--replay PATH] [--headless [--runs N] [--duration SECONDS]
+    /// [--output PATH]]`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut seed = None;
+        let mut headless = false;
+        let mut runs = 1;
+        let mut max_duration = DEFAULT_MAX_DURATION;
+        let mut output: Option<PathBuf> = None;
+        let mut replay = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+            match arg.as_str() {
+                "--seed" => seed = Some(parse(&arg, &value(&arg)?)?),
+                "--headless" => headless = true,
+                "--runs" => runs = parse(&arg, &value(&arg)?)?,
+                "--duration" => max_duration = parse(&arg, &value(&arg)?)?,
+                "--output" => output = Some(value(&arg)?.into()),
+                "--replay" => replay = Some(value(&arg)?.into()),
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+
+        let headless = headless.then(|| HeadlessOptions {
+            runs,
+            seed: seed.unwrap_or(0),
+            max_duration,
+            output,
+        });
+        Ok(Self {
+            seed,
+            headless,
+            replay,
+        })
+    }
+}
+
+fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value} for {name}"))
+}
@@ -0,0 +1,151 @@
+use crate::prelude::*;
+
+use bevy::reflect::TypePath;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::{Deserialize, Deserializer};
+
+/// Schedule of what spawns when over the course of a run
+#[derive(Asset, TypePath, Deserialize)]
+pub struct WaveTimeline {
+    pub waves: Vec<Wave>,
+    /// In the order they appear, however the file lists them
+    #[serde(deserialize_with = "bosses_by_time")]
+    pub bosses: Vec<BossEvent>,
+}
+
+impl WaveTimeline {
+    /// Wave active at `time`, holding on the last wave once the timeline runs out
+    pub fn wave_at(&self, time: f32) -> Option<&Wave> {
+        self.waves
+            .iter()
+            .find(|wave| wave.start <= time && time < wave.end)
+            .or_else(|| self.waves.iter().rev().find(|wave| wave.start <= time))
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Wave {
+    /// Run time in seconds the wave starts at
+    pub start: f32,
+    /// Run time in seconds the wave ends at
+    pub end: f32,
+    /// Mob kinds spawned during the wave, picked by weight
+    pub mobs: Vec<WaveMob>,
+    /// Mobs spawned per second
+    pub spawn_rate: f32,
+    /// Spawning holds off while this many mobs are alive
+    pub max_alive: u32,
+    /// Chance each spawn is an elite
+    #[serde(default)]
+    pub elite_chance: f32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WaveMob {
+    pub kind: String,
+    pub weight: f32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct BossEvent {
+    /// Run time in seconds the boss appears at
+    pub time: f32,
+    pub kind: String,
+}
+
+fn bosses_by_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<BossEvent>, D::Error> {
+    let mut bosses = Vec::<BossEvent>::deserialize(deserializer)?;
+    bosses.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(bosses)
+}
+
+#[derive(Resource, Default)]
+pub struct WaveDirector {
+    /// Seconds since the run started
+    pub elapsed: f32,
+    /// Fractional spawns carried over between frames
+    spawn_budget: f32,
+    /// Index of the next boss event that has not fired yet
+    next_boss: usize,
+}
+
+pub struct DirectorPlugin;
+
+impl Plugin for DirectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<WaveTimeline>()
+            .register_asset_loader(RonAssetLoader::<WaveTimeline>::new(&["waves.ron"]))
+            .init_resource::<WaveDirector>()
+            .add_systems(OnExit(AppState::MainMenu), reset_director)
+            .add_systems(
+                FixedUpdate,
+                direct_waves
+                    .after(count_mobs)
+                    .before(spawn_mobs)
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+pub fn reset_director(mut commands: Commands) {
+    commands.insert_resource(WaveDirector::default());
+}
+
+pub fn direct_waves(
+    time: Res<Time>,
+    mut director: ResMut<WaveDirector>,
+    data: Res<DataAssets>,
+    timelines: Res<Assets<WaveTimeline>>,
+    count: Res<MobCount>,
+    mut rng: ResMut<GameRng>,
+    mut spawn_events: EventWriter<SpawnMob>,
+) {
+    let Some(timeline) = timelines.get(&data.waves) else {
+        return;
+    };
+
+    director.elapsed += time.delta_seconds();
+    let elapsed = director.elapsed;
+
+    while let Some(boss) = timeline.bosses.get(director.next_boss) {
+        if boss.time > elapsed {
+            break;
+        }
+        spawn_events.send(SpawnMob {
+            kind: boss.kind.clone(),
+            rank: MobRank::Boss,
+            position: None,
+        });
+        director.next_boss += 1;
+    }
+
+    let Some(wave) = timeline.wave_at(elapsed) else {
+        return;
+    };
+    let Ok(weights) = WeightedIndex::new(wave.mobs.iter().map(|mob| mob.weight)) else {
+        return;
+    };
+
+    director.spawn_budget += wave.spawn_rate * time.delta_seconds();
+    let mut alive = count.0;
+    while director.spawn_budget >= 1.0 && alive < wave.max_alive {
+        let rng = rng.stream("waves");
+        let kind = wave.mobs[weights.sample(rng)].kind.clone();
+        let elite = wave.elite_chance > 0.0 && rng.gen_bool(wave.elite_chance.min(1.0) as f64);
+        spawn_events.send(SpawnMob {
+            kind,
+            rank: if elite {
+                MobRank::Elite
+            } else {
+                MobRank::Normal
+            },
+            position: None,
+        });
+        director.spawn_budget -= 1.0;
+        alive += 1;
+    }
+    // Don't bank spawns while capped, or they all arrive at once when mobs die
+    director.spawn_budget = director.spawn_budget.min(1.0);
+}
@@ -9,7 +9,13 @@ impl Plugin for FpsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(FrameTimeDiagnosticsPlugin)
             .add_systems(Startup, setup_fps_counter)
-            .add_systems(Update, (fps_text_update_system, fps_counter_showhide));
+            .add_systems(
+                Update,
+                (
+                    fps_text_update_system.run_if(fixed_cadence("fps_text", 0.25)),
+                    fps_counter_showhide,
+                ),
+            );
     }
 }
 
@@ -127,9 +133,9 @@ fn fps_text_update_system(
     }
 }
 
-/// Toggle the FPS counter when pressing F12
-fn fps_counter_showhide(mut q: Query<&mut Visibility, With<FpsRoot>>, kbd: Res<Input<KeyCode>>) {
-    if kbd.just_pressed(KeyCode::F12) {
+/// Toggle the FPS counter when pressing the `ToggleFps` binding
+fn fps_counter_showhide(mut q: Query<&mut Visibility, With<FpsRoot>>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::ToggleFps) {
         let mut vis = q.single_mut();
         *vis = match *vis {
             Visibility::Hidden => Visibility::Visible,
@@ -0,0 +1,275 @@
+use crate::prelude::*;
+
+use bevy::app::AppExit;
+use bevy::input::InputPlugin;
+use bevy::log::{Level, LogPlugin};
+use bevy::time::TimeUpdateStrategy;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Runs still going after this many seconds are cut short
+pub const DEFAULT_MAX_DURATION: f32 = 1800.0;
+/// Mobs closer than this push the scripted player away
+const FLEE_RADIUS: f32 = 300.0;
+/// Mobs closer than this make the scripted player dash away
+const DASH_RADIUS: f32 = 80.0;
+const GEM_SEEK_RADIUS: f32 = 600.0;
+/// How strongly the nearest gem pulls the scripted player compared to mobs pushing it away
+const GEM_WEIGHT: f32 = 0.5;
+/// Radians per second the scripted player turns while wandering with nothing around
+const WANDER_TURN_RATE: f32 = 0.3;
+
+/// Settings for a batch of headless runs
+#[derive(Resource, Clone)]
+pub struct HeadlessOptions {
+    pub runs: u32,
+    /// Seed of the first run, each later run uses the next seed up
+    pub seed: u64,
+    pub max_duration: f32,
+    /// Where to write the JSON report, stdout when missing
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+pub struct RunReport {
+    pub seed: u64,
+    /// Seconds survived, capped at the maximum run duration
+    pub survival_time: f32,
+    pub died: bool,
+    pub kills: u32,
+    pub damage_taken: f32,
+    pub level: u32,
+}
+
+#[derive(Resource, Default, Serialize)]
+pub struct HeadlessReport {
+    pub runs: Vec<RunReport>,
+}
+
+/// Running totals for the run in progress
+#[derive(Resource, Default)]
+pub struct RunTally {
+    kills: u32,
+    damage_taken: f32,
+    last_health: Option<f32>,
+}
+
+/// Plays runs back to back with a scripted player, then writes a report and exits
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HeadlessReport>()
+            .init_resource::<RunTally>()
+            .add_systems(Update, start_next_run.run_if(in_state(AppState::MainMenu)))
+            .add_systems(OnExit(AppState::MainMenu), reset_tally)
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        scripted_player.before(update_dash).before(player_movement),
+                        tally_kills.after(apply_damage).before(despawn_dead_mobs),
+                        tally_damage.after(apply_damage),
+                        end_long_runs,
+                    )
+                        .run_if(in_state(AppState::Playing)),
+                    pick_first_upgrade
+                        .before(apply_upgrade)
+                        .run_if(in_state(AppState::LevelUp)),
+                    leave_chests
+                        .before(close_chest)
+                        .run_if(in_state(AppState::ChestOpening)),
+                )
+                    .in_set(TickSet::Gameplay),
+            )
+            .add_systems(OnEnter(AppState::GameOver), record_run);
+    }
+}
+
+/// The game without a window or rendering, for headless runs and replay checks. Every update
+/// advances time by exactly one tick, so runs go as fast as they can be simulated.
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        LogPlugin {
+            level: Level::WARN,
+            ..default()
+        },
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        InputPlugin,
+    ))
+    .init_asset::<AnimationClip>()
+    .init_asset::<SpriteSheet>()
+    .add_event::<AnimationFinished>()
+    .add_plugins(crate::GamePlugins)
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        TIMESTEP,
+    )))
+    .add_systems(
+        Update,
+        exit_on_load_failure
+            .after(track_loading)
+            .run_if(in_state(AppState::Loading)),
+    );
+    app
+}
+
+/// Nobody is there to read the loading screen, so give up once `track_loading` has logged the
+/// assets that failed
+fn exit_on_load_failure(progress: Res<LoadingProgress>) {
+    if progress.has_failed() {
+        std::process::exit(1);
+    }
+}
+
+/// Runs the game without a window until every run in `options` has finished
+pub fn run(options: HeadlessOptions) {
+    headless_app()
+        .insert_resource(options)
+        .add_plugins(HeadlessPlugin)
+        .run();
+}
+
+pub fn start_next_run(
+    options: Res<HeadlessOptions>,
+    report: Res<HeadlessReport>,
+    mut run_seed: ResMut<RunSeed>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let index = report.runs.len() as u32;
+    if index >= options.runs {
+        write_report(&options, &report);
+        exit.send(AppExit);
+        return;
+    }
+
+    run_seed.0 = Some(options.seed.wrapping_add(index as u64));
+    next_state.set(AppState::Playing);
+}
+
+fn write_report(options: &HeadlessOptions, report: &HeadlessReport) {
+    let json = match serde_json::to_string_pretty(report) {
+        Ok(json) => json,
+        Err(error) => {
+            error!("Could not serialize the headless report: {error}");
+            return;
+        }
+    };
+    match &options.output {
+        Some(path) => {
+            if let Err(error) = std::fs::write(path, json) {
+                error!("Could not write the report to {}: {error}", path.display());
+            }
+        }
+        None => println!("{json}"),
+    }
+}
+
+pub fn reset_tally(mut commands: Commands) {
+    commands.insert_resource(RunTally::default());
+}
+
+/// Runs from mobs closing in and towards the nearest gem, wandering when neither is around,
+/// and dashes when a mob gets too close
+pub fn scripted_player(
+    director: Res<WaveDirector>,
+    mob_grid: Res<SpatialGrid<Mob>>,
+    gem_grid: Res<SpatialGrid<XpGem>>,
+    mut input: ResMut<PlayerInput>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let position = player.translation.xy();
+
+    let mut flee = Vec2::ZERO;
+    for (_, mob) in mob_grid.within_radius(position, FLEE_RADIUS) {
+        let offset = position - mob;
+        flee += offset.normalize_or_zero() * (1.0 - offset.length() / FLEE_RADIUS);
+    }
+    let seek = gem_grid
+        .nearest(position, GEM_SEEK_RADIUS)
+        .map(|(_, gem)| (gem - position).normalize_or_zero())
+        .unwrap_or(Vec2::ZERO);
+
+    input.dash = mob_grid.nearest(position, DASH_RADIUS).is_some();
+    let direction = flee + seek * GEM_WEIGHT;
+    input.movement = if direction.length_squared() > f32::EPSILON {
+        direction.normalize()
+    } else {
+        Vec2::from_angle(director.elapsed * WANDER_TURN_RATE)
+    };
+}
+
+pub fn tally_kills(
+    mut tally: ResMut<RunTally>,
+    mut death_events: EventReader<DeathEvent>,
+    mob_query: Query<(), With<Mob>>,
+) {
+    for event in death_events.read() {
+        if mob_query.contains(event.entity) {
+            tally.kills += 1;
+        }
+    }
+}
+
+pub fn tally_damage(mut tally: ResMut<RunTally>, player_query: Query<&Health, With<Player>>) {
+    let Ok(health) = player_query.get_single() else {
+        return;
+    };
+    if let Some(last_health) = tally.last_health {
+        if health.current < last_health {
+            tally.damage_taken += last_health - health.current;
+        }
+    }
+    tally.last_health = Some(health.current);
+}
+
+pub fn end_long_runs(
+    options: Res<HeadlessOptions>,
+    director: Res<WaveDirector>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if director.elapsed >= options.max_duration {
+        next_state.set(AppState::GameOver);
+    }
+}
+
+pub fn pick_first_upgrade(mut chosen_events: EventWriter<UpgradeChosen>) {
+    chosen_events.send(UpgradeChosen(0));
+}
+
+/// Asks to close every frame, so the chest screen closes as soon as its reveal is done
+pub fn leave_chests(mut closed_events: EventWriter<ChestClosed>) {
+    closed_events.send(ChestClosed);
+}
+
+pub fn record_run(
+    options: Res<HeadlessOptions>,
+    director: Res<WaveDirector>,
+    rng: Res<GameRng>,
+    tally: Res<RunTally>,
+    mut report: ResMut<HeadlessReport>,
+    player_query: Query<(&Health, &Experience), With<Player>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let (died, level) = player_query
+        .get_single()
+        .map(|(health, experience)| (health.is_dead(), experience.level))
+        .unwrap_or((true, 0));
+    report.runs.push(RunReport {
+        seed: rng.seed(),
+        survival_time: director.elapsed.min(options.max_duration),
+        died,
+        kills: tally.kills,
+        damage_taken: tally.damage_taken,
+        level,
+    });
+    next_state.set(AppState::MainMenu);
+}
@@ -0,0 +1,187 @@
+use crate::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use bevy::utils::HashSet;
+
+/// Impulse the player takes away from a mob touching it
+const CONTACT_KNOCKBACK: f32 = 400.0;
+
+#[derive(Component)]
+pub struct Health {
+    pub max: f32,
+    pub current: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { max, current: max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// Damage dealt to the player every time it touches this entity
+#[derive(Component)]
+pub struct ContactDamage(pub f32);
+
+/// Seconds of invulnerability granted after taking a hit
+#[derive(Component)]
+pub struct HitInvulnerability(pub f32);
+
+/// Present while an entity ignores incoming damage
+#[derive(Component, Deref, DerefMut)]
+pub struct Invulnerable(pub Timer);
+
+#[derive(Event)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    /// Impulse given to the target if the hit lands
+    pub knockback: Vec2,
+    /// Burns and poisons, which tick through invulnerability without granting any, so they
+    /// can neither be dodged nor shield the target from real hits
+    pub over_time: bool,
+}
+
+#[derive(Event)]
+pub struct DeathEvent {
+    pub entity: Entity,
+}
+
+/// Collider pairs currently touching, as reported by rapier
+#[derive(Resource, Default)]
+pub struct Contacts(pub HashSet<(Entity, Entity)>);
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
+            .init_resource::<Contacts>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    track_contacts,
+                    apply_contact_damage,
+                    apply_damage,
+                    tick_invulnerability,
+                )
+                    .chain()
+                    .before(apply_movement)
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+fn contact_pair(a: Entity, b: Entity) -> (Entity, Entity) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+pub fn track_contacts(mut events: EventReader<CollisionEvent>, mut contacts: ResMut<Contacts>) {
+    for event in events.read() {
+        match event {
+            CollisionEvent::Started(a, b, _) => {
+                contacts.0.insert(contact_pair(*a, *b));
+            }
+            CollisionEvent::Stopped(a, b, _) => {
+                contacts.0.remove(&contact_pair(*a, *b));
+            }
+        }
+    }
+}
+
+pub fn apply_contact_damage(
+    contacts: Res<Contacts>,
+    mut damage_events: EventWriter<DamageEvent>,
+    attacker_query: Query<(&ContactDamage, &Transform), With<Mob>>,
+    target_query: Query<&Transform, (With<Player>, Without<Invulnerable>)>,
+) {
+    for (a, b) in contacts.0.iter() {
+        for (attacker, target) in [(*a, *b), (*b, *a)] {
+            if let (Ok((damage, attacker_transform)), Ok(target_transform)) =
+                (attacker_query.get(attacker), target_query.get(target))
+            {
+                let away = (target_transform.translation.xy()
+                    - attacker_transform.translation.xy())
+                .normalize_or_zero();
+                damage_events.send(DamageEvent {
+                    target,
+                    amount: damage.0,
+                    knockback: away * CONTACT_KNOCKBACK,
+                    over_time: false,
+                });
+            }
+        }
+    }
+}
+
+pub fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut query: Query<(&mut Health, Option<&HitInvulnerability>, Has<Invulnerable>)>,
+    mut controller_query: Query<&mut MovementController>,
+    stats_query: Query<&Stats>,
+    mut hit_this_frame: Local<HashSet<Entity>>,
+) {
+    hit_this_frame.clear();
+    for event in damage_events.read() {
+        // Invulnerable is inserted through commands, so guard against several hits in one frame
+        if hit_this_frame.contains(&event.target) && !event.over_time {
+            continue;
+        }
+        if let Ok((mut health, invulnerability, invulnerable)) = query.get_mut(event.target) {
+            if health.is_dead() || (invulnerable && !event.over_time) {
+                continue;
+            }
+
+            let armor = stats_query
+                .get(event.target)
+                .map_or(0.0, |stats| stats.get(Stat::Armor));
+            let damage = (event.amount - armor).max(0.0);
+            // Hits armor soaks up entirely glance off, without knockback or invulnerability
+            if damage <= 0.0 {
+                continue;
+            }
+            health.current = (health.current - damage).max(0.0);
+            if let Ok(mut controller) = controller_query.get_mut(event.target) {
+                controller.apply_impulse(event.knockback);
+            }
+
+            if health.is_dead() {
+                death_events.send(DeathEvent {
+                    entity: event.target,
+                });
+            } else if let Some(invulnerability) = invulnerability.filter(|_| !event.over_time) {
+                hit_this_frame.insert(event.target);
+                commands
+                    .entity(event.target)
+                    .insert(Invulnerable(Timer::from_seconds(
+                        invulnerability.0,
+                        TimerMode::Once,
+                    )));
+            }
+        }
+    }
+}
+
+pub fn tick_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable)>,
+) {
+    for (entity, mut invulnerable) in &mut query {
+        invulnerable.tick(time.delta());
+        if invulnerable.finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use crate::prelude::*;
+
+use bevy::input::InputSystem;
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Player's bindings, written whenever they are changed on the controls screen
+const INPUT_MAP_PATH: &str = "settings/input.ron";
+
+/// What the player wants to do this frame, written from the bound actions, the scripted player
+/// or a replay
+#[derive(Resource, Default)]
+pub struct PlayerInput {
+    /// Desired movement direction, at most unit length
+    pub movement: Vec2,
+    /// Whether a dash was asked for this frame
+    pub dash: bool,
+}
+
+/// Things the player can do, whichever keys or gamepad inputs they are bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveAxis,
+    Pause,
+    ToggleFps,
+    Confirm,
+    Dash,
+    PreviousChoice,
+    NextChoice,
+    Quit,
+}
+
+/// One rebindable input: a button action, or one direction of `MoveAxis`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Control {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Pause,
+    ToggleFps,
+    Confirm,
+    Dash,
+    PreviousChoice,
+    NextChoice,
+    Quit,
+}
+
+/// Every control, in the order they are listed on the controls screen
+pub const CONTROLS: [Control; 11] = [
+    Control::MoveUp,
+    Control::MoveDown,
+    Control::MoveLeft,
+    Control::MoveRight,
+    Control::Dash,
+    Control::Pause,
+    Control::Confirm,
+    Control::PreviousChoice,
+    Control::NextChoice,
+    Control::ToggleFps,
+    Control::Quit,
+];
+
+impl Control {
+    pub fn action(self) -> Action {
+        match self {
+            Control::MoveUp | Control::MoveDown | Control::MoveLeft | Control::MoveRight => {
+                Action::MoveAxis
+            }
+            Control::Pause => Action::Pause,
+            Control::ToggleFps => Action::ToggleFps,
+            Control::Confirm => Action::Confirm,
+            Control::Dash => Action::Dash,
+            Control::PreviousChoice => Action::PreviousChoice,
+            Control::NextChoice => Action::NextChoice,
+            Control::Quit => Action::Quit,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Control::MoveUp => "Move up",
+            Control::MoveDown => "Move down",
+            Control::MoveLeft => "Move left",
+            Control::MoveRight => "Move right",
+            Control::Pause => "Pause",
+            Control::ToggleFps => "Toggle FPS",
+            Control::Confirm => "Confirm",
+            Control::Dash => "Dash",
+            Control::PreviousChoice => "Previous choice",
+            Control::NextChoice => "Next choice",
+            Control::Quit => "Quit",
+        }
+    }
+
+    /// Direction this control pushes `MoveAxis` in, zero for button actions
+    fn direction(self) -> Vec2 {
+        match self {
+            Control::MoveUp => Vec2::Y,
+            Control::MoveDown => Vec2::NEG_Y,
+            Control::MoveLeft => Vec2::NEG_X,
+            Control::MoveRight => Vec2::X,
+            _ => Vec2::ZERO,
+        }
+    }
+}
+
+/// A key or gamepad button a control can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn label(self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::Button(button) => format!("Pad {button:?}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+impl Stick {
+    fn axes(self) -> (GamepadAxisType, GamepadAxisType) {
+        match self {
+            Stick::Left => (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+            Stick::Right => (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+        }
+    }
+}
+
+/// Which keys and gamepad inputs trigger each action, saved to `settings/input.ron`
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Control, Vec<Binding>>,
+    /// Gamepad stick driving `MoveAxis`, alongside the movement bindings
+    pub move_stick: Stick,
+    /// Fraction of full stick deflection ignored around the centre
+    pub deadzone: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::{Button, Key};
+        use GamepadButtonType::*;
+
+        let bindings = [
+            (
+                Control::MoveUp,
+                vec![Key(KeyCode::W), Key(KeyCode::Up), Button(DPadUp)],
+            ),
+            (
+                Control::MoveDown,
+                vec![Key(KeyCode::S), Key(KeyCode::Down), Button(DPadDown)],
+            ),
+            (
+                Control::MoveLeft,
+                vec![Key(KeyCode::A), Key(KeyCode::Left), Button(DPadLeft)],
+            ),
+            (
+                Control::MoveRight,
+                vec![Key(KeyCode::D), Key(KeyCode::Right), Button(DPadRight)],
+            ),
+            (Control::Pause, vec![Key(KeyCode::P), Button(Start)]),
+            (Control::ToggleFps, vec![Key(KeyCode::F12), Button(Select)]),
+            (Control::Confirm, vec![Key(KeyCode::Return), Button(South)]),
+            (Control::Dash, vec![Key(KeyCode::Space), Button(East)]),
+            (
+                Control::PreviousChoice,
+                vec![Key(KeyCode::Q), Button(LeftTrigger)],
+            ),
+            (
+                Control::NextChoice,
+                vec![Key(KeyCode::E), Button(RightTrigger)],
+            ),
+            (Control::Quit, vec![Key(KeyCode::Escape)]),
+        ];
+        Self {
+            bindings: bindings.into_iter().collect(),
+            move_stick: Stick::Left,
+            deadzone: 0.2,
+        }
+    }
+}
+
+impl InputMap {
+    /// Loads the saved bindings, falling back to the defaults when there are none. Controls added
+    /// since the bindings were saved get whichever of their defaults are still free.
+    pub fn load(path: &Path) -> Self {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        let mut input_map: Self = ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Ignoring bindings in {}: {error}", path.display());
+            Self::default()
+        });
+        for (control, defaults) in Self::default().bindings {
+            if input_map.bindings.contains_key(&control) {
+                continue;
+            }
+            // Skipping any the player has already bound to something else
+            let free = defaults
+                .into_iter()
+                .filter(|binding| {
+                    !input_map
+                        .bindings
+                        .values()
+                        .any(|bindings| bindings.contains(binding))
+                })
+                .collect();
+            input_map.bindings.insert(control, free);
+        }
+        input_map
+    }
+
+    pub fn save(&self, path: &Path) {
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|error| error.to_string())
+            .and_then(|contents| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+                }
+                std::fs::write(path, contents).map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            error!("Could not save bindings to {}: {error}", path.display());
+        }
+    }
+
+    pub fn bindings(&self, control: Control) -> &[Binding] {
+        self.bindings.get(&control).map_or(&[], Vec::as_slice)
+    }
+
+    /// Binds `control` to `binding` in place of its other bindings of the same kind, taking it
+    /// away from any control it was bound to before
+    pub fn rebind(&mut self, control: Control, binding: Binding) {
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|existing| *existing != binding);
+        }
+        let bindings = self.bindings.entry(control).or_default();
+        bindings.retain(|existing| {
+            !matches!(
+                (existing, binding),
+                (Binding::Key(_), Binding::Key(_)) | (Binding::Button(_), Binding::Button(_))
+            )
+        });
+        bindings.insert(0, binding);
+    }
+
+    /// Bindings of `control` as shown on the controls screen
+    pub fn describe(&self, control: Control) -> String {
+        let labels: Vec<String> = self
+            .bindings(control)
+            .iter()
+            .map(|binding| binding.label())
+            .collect();
+        if labels.is_empty() {
+            "Unbound".to_string()
+        } else {
+            labels.join(", ")
+        }
+    }
+}
+
+/// Actions the bound inputs trigger this frame
+#[derive(Resource, Default)]
+pub struct ActionState {
+    movement: Vec2,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    /// `MoveAxis` as a direction of at most unit length, shorter when a stick is half pushed
+    pub fn movement(&self) -> Vec2 {
+        self.movement
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// Resolves the keyboard and any gamepads into actions through the `InputMap`
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load(Path::new(INPUT_MAP_PATH)))
+            .init_resource::<ActionState>()
+            .add_systems(PreUpdate, update_action_state.after(InputSystem));
+    }
+}
+
+/// Saves the bindings where `InputMapPlugin` will load them next time
+pub fn save_input_map(input_map: &InputMap) {
+    input_map.save(Path::new(INPUT_MAP_PATH));
+}
+
+pub fn update_action_state(
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut actions: ResMut<ActionState>,
+) {
+    let pressed = |binding: &Binding| match *binding {
+        Binding::Key(key) => keyboard_input.pressed(key),
+        Binding::Button(button) => gamepads
+            .iter()
+            .any(|gamepad| gamepad_buttons.pressed(GamepadButton::new(gamepad, button))),
+    };
+    let just_pressed = |binding: &Binding| match *binding {
+        Binding::Key(key) => keyboard_input.just_pressed(key),
+        Binding::Button(button) => gamepads
+            .iter()
+            .any(|gamepad| gamepad_buttons.just_pressed(GamepadButton::new(gamepad, button))),
+    };
+
+    actions.just_pressed.clear();
+    let mut digital = Vec2::ZERO;
+    for control in CONTROLS {
+        let bindings = input_map.bindings(control);
+        if bindings.iter().any(pressed) {
+            digital += control.direction();
+        }
+        if bindings.iter().any(just_pressed) {
+            actions.just_pressed.insert(control.action());
+        }
+    }
+
+    // The stick wins over keys and the d-pad whenever it is pushed past the deadzone
+    let (x_axis, y_axis) = input_map.move_stick.axes();
+    let analog = gamepads
+        .iter()
+        .map(|gamepad| {
+            let x = gamepad_axes.get(GamepadAxis::new(gamepad, x_axis));
+            let y = gamepad_axes.get(GamepadAxis::new(gamepad, y_axis));
+            apply_deadzone(
+                Vec2::new(x.unwrap_or(0.0), y.unwrap_or(0.0)),
+                input_map.deadzone,
+            )
+        })
+        .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+        .unwrap_or(Vec2::ZERO);
+
+    actions.movement = if analog != Vec2::ZERO {
+        analog
+    } else {
+        digital.normalize_or_zero()
+    };
+}
+
+/// Drops stick input inside a circular deadzone and rescales the rest, so movement ramps up
+/// from zero at its edge rather than jumping straight to the deadzone's speed
+fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= deadzone {
+        return Vec2::ZERO;
+    }
+    let scaled = ((length - deadzone) / (1.0 - deadzone).max(f32::EPSILON)).min(1.0);
+    stick / length * scaled
+}
+
+/// Drives `PlayerInput` from the bound actions, for a player at the keyboard or a gamepad
+pub struct ActionInputPlugin;
+
+impl Plugin for ActionInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, read_actions.run_if(in_state(AppState::Playing)))
+            .add_systems(FixedUpdate, release_dash.after(TickSet::Record));
+    }
+}
+
+/// Movement follows the latest frame, while a dash pressed on any frame is held for the next tick
+pub fn read_actions(actions: Res<ActionState>, mut input: ResMut<PlayerInput>) {
+    input.movement = actions.movement();
+    input.dash |= actions.just_pressed(Action::Dash);
+}
+
+pub fn release_dash(mut input: ResMut<PlayerInput>) {
+    input.dash = false;
+}
@@ -0,0 +1,141 @@
+use crate::prelude::*;
+
+use bevy::reflect::TypePath;
+use serde::Deserialize;
+
+/// Every passive item that can be picked up, loaded from a `.passives.ron` file
+#[derive(Asset, TypePath, Deserialize)]
+pub struct PassiveCatalogue {
+    pub passives: Vec<PassiveDef>,
+}
+
+impl PassiveCatalogue {
+    pub fn get(&self, id: &str) -> Option<&PassiveDef> {
+        self.passives.iter().find(|passive| passive.id == id)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct PassiveDef {
+    pub id: String,
+    pub name: String,
+    pub colour: (f32, f32, f32),
+    pub stat: Stat,
+    /// Modifier on `stat` at each level, starting at level 1
+    pub levels: Vec<Modifier>,
+}
+
+impl PassiveDef {
+    pub fn max_level(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    pub fn colour(&self) -> Color {
+        Color::rgb(self.colour.0, self.colour.1, self.colour.2)
+    }
+}
+
+pub struct PassiveItem {
+    pub id: String,
+    pub level: u32,
+}
+
+/// Passive items the player holds, and how many weapons and passives it can hold at once
+#[derive(Component)]
+pub struct Inventory {
+    pub weapon_slots: usize,
+    pub passive_slots: usize,
+    pub passives: Vec<PassiveItem>,
+}
+
+impl Inventory {
+    pub fn new(weapon_slots: usize, passive_slots: usize) -> Self {
+        Self {
+            weapon_slots,
+            passive_slots,
+            passives: Vec::new(),
+        }
+    }
+
+    pub fn passive(&self, id: &str) -> Option<&PassiveItem> {
+        self.passives.iter().find(|passive| passive.id == id)
+    }
+
+    pub fn has_free_passive_slot(&self) -> bool {
+        self.passives.len() < self.passive_slots
+    }
+
+    /// Adds the passive or raises it to `level`, swapping its stat modifier for that level's
+    pub fn equip_passive(&mut self, stats: &mut Stats, def: &PassiveDef, level: u32) {
+        match self
+            .passives
+            .iter_mut()
+            .find(|passive| passive.id == def.id)
+        {
+            Some(passive) => passive.level = level,
+            None => self.passives.push(PassiveItem {
+                id: def.id.clone(),
+                level,
+            }),
+        }
+        let index = level.clamp(1, def.max_level().max(1)) - 1;
+        stats.set_modifier(
+            ModifierSource::Item(def.id.clone()),
+            def.stat,
+            def.levels.get(index as usize).copied(),
+        );
+    }
+}
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<PassiveCatalogue>()
+            .register_asset_loader(RonAssetLoader::<PassiveCatalogue>::new(&["passives.ron"]))
+            .add_systems(
+                FixedUpdate,
+                evolve_weapons
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// Swaps each max level weapon for its evolution once the player holds the passive it needs
+pub fn evolve_weapons(
+    mut commands: Commands,
+    data: Res<DataAssets>,
+    catalogues: Res<Assets<WeaponCatalogue>>,
+    player_query: Query<(Entity, &Inventory, &Stats), With<Player>>,
+    weapon_query: Query<(Entity, &Weapon)>,
+) {
+    let (Ok((player, inventory, stats)), Some(catalogue)) =
+        (player_query.get_single(), catalogues.get(&data.weapons))
+    else {
+        return;
+    };
+
+    for (entity, weapon) in weapon_query.iter() {
+        let Some(def) = catalogue.get(&weapon.id) else {
+            continue;
+        };
+        let Some(evolution) = &def.evolution else {
+            continue;
+        };
+        if weapon.level < def.max_level() || inventory.passive(&evolution.passive).is_none() {
+            continue;
+        }
+        let Some(evolved) = catalogue.get(&evolution.into) else {
+            warn!(
+                "{} evolves into {:?}, which is not in the weapon catalogue",
+                def.name, evolution.into
+            );
+            continue;
+        };
+
+        info!("{} evolved into {}", def.name, evolved.name);
+        commands.entity(entity).despawn_recursive();
+        spawn_weapon(&mut commands, player, stats, evolved, 1);
+    }
+}
@@ -0,0 +1,101 @@
+pub mod animation;
+pub mod animation_state;
+pub mod aseprite;
+pub mod assets;
+pub mod boss;
+pub mod cadence;
+pub mod cli;
+pub mod director;
+pub mod fps;
+pub mod headless;
+pub mod health;
+pub mod input;
+pub mod inventory;
+pub mod loot;
+pub mod menu;
+pub mod mob;
+pub mod mob_behaviour;
+pub mod movement;
+pub mod physics;
+pub mod player;
+pub mod replay;
+pub mod rng;
+pub mod spatial;
+pub mod state;
+pub mod stats;
+pub mod status;
+pub mod transform_clip;
+pub mod ui;
+pub mod upgrade;
+pub mod weapon;
+pub mod weapon_patterns;
+pub mod xp;
+
+pub mod prelude {
+    pub use crate::animation::*;
+    pub use crate::animation_state::*;
+    pub use crate::aseprite::*;
+    pub use crate::assets::*;
+    pub use crate::boss::*;
+    pub use crate::cadence::*;
+    pub use crate::cli::*;
+    pub use crate::director::*;
+    pub use crate::fps::*;
+    pub use crate::headless::*;
+    pub use crate::health::*;
+    pub use crate::input::*;
+    pub use crate::inventory::*;
+    pub use crate::loot::*;
+    pub use crate::menu::*;
+    pub use crate::mob::*;
+    pub use crate::mob_behaviour::*;
+    pub use crate::movement::*;
+    pub use crate::physics::*;
+    pub use crate::player::*;
+    pub use crate::replay::*;
+    pub use crate::rng::*;
+    pub use crate::spatial::*;
+    pub use crate::state::*;
+    pub use crate::stats::*;
+    pub use crate::status::*;
+    pub use crate::transform_clip::*;
+    pub use crate::ui::*;
+    pub use crate::upgrade::*;
+    pub use crate::weapon::*;
+    pub use crate::weapon_patterns::*;
+    pub use crate::xp::*;
+    pub use bevy::prelude::*;
+}
+
+use crate::prelude::*;
+use bevy::app::PluginGroupBuilder;
+
+pub const BG_COLOUR: Color = Color::rgb(0.06, 0.06, 0.06);
+
+/// Gameplay shared by the windowed game and headless runs
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(StatePlugin)
+            .add(CadencePlugin)
+            .add(RngPlugin)
+            .add(PhysicsPlugin)
+            .add(MovementPlugin)
+            .add(AssetsPlugin)
+            .add(HealthPlugin)
+            .add(StatusPlugin)
+            .add(StatsPlugin)
+            .add(PlayerPlugin)
+            .add(MobPlugin)
+            .add(MobBehaviourPlugin)
+            .add(BossPlugin)
+            .add(DirectorPlugin)
+            .add(WeaponPlugin)
+            .add(XpPlugin)
+            .add(UpgradePlugin)
+            .add(InventoryPlugin)
+            .add(LootPlugin)
+    }
+}
@@ -0,0 +1,493 @@
+use crate::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use bevy::reflect::TypePath;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+const CHEST_SIZE: f32 = 36.0;
+/// Seconds before the first upgrade in a chest is revealed, then between each one after it
+const REVEAL_INTERVAL: f32 = 0.6;
+/// Seconds a card takes to pop up to full size once revealed
+const REVEAL_POP: f32 = 0.35;
+/// Size a card pops up from
+const REVEAL_POP_SCALE: f32 = 0.6;
+const CARD_HIDDEN_COLOUR: Color = Color::rgb(0.1, 0.1, 0.1);
+const CARD_COLOUR: Color = Color::rgb(0.2, 0.17, 0.08);
+
+/// Chests elite and boss mobs drop, loaded from a `.loot.ron` file
+#[derive(Asset, TypePath, Deserialize)]
+pub struct LootTable {
+    pub chests: Vec<ChestLoot>,
+}
+
+impl LootTable {
+    pub fn for_rank(&self, rank: MobRank) -> Option<&ChestLoot> {
+        self.chests.iter().find(|chest| chest.rank == rank)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ChestLoot {
+    /// Mobs of this rank drop the chest
+    pub rank: MobRank,
+    /// Chance one of those mobs drops the chest when killed
+    pub drop_chance: f32,
+    pub colour: (f32, f32, f32),
+    /// Number of upgrades the chest holds, picked by weight
+    pub upgrades: Vec<UpgradeCount>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct UpgradeCount {
+    pub count: u32,
+    pub weight: f32,
+}
+
+impl ChestLoot {
+    pub fn colour(&self) -> Color {
+        Color::rgb(self.colour.0, self.colour.1, self.colour.2)
+    }
+
+    /// Number of upgrades a dropped chest holds, always at least one
+    pub fn roll_upgrades(&self, rng: &mut impl Rng) -> u32 {
+        WeightedIndex::new(self.upgrades.iter().map(|upgrades| upgrades.weight))
+            .map(|index| self.upgrades[index.sample(rng)].count.max(1))
+            .unwrap_or(1)
+    }
+}
+
+/// Chest lying where a mob died, opened once the player touches it
+#[derive(Component)]
+pub struct Chest {
+    pub upgrades: u32,
+}
+
+/// Upgrade counts of chests picked up but not opened yet, opened one after another
+#[derive(Resource, Default)]
+pub struct PendingChests(pub VecDeque<u32>);
+
+/// Upgrades in the chest being opened and how many of them have been shown so far. They are
+/// granted as soon as the chest opens, the reveal is only for show.
+#[derive(Resource)]
+pub struct ChestReveal {
+    pub upgrades: usize,
+    pub revealed: usize,
+    timer: Timer,
+}
+
+impl ChestReveal {
+    pub fn is_finished(&self) -> bool {
+        self.revealed >= self.upgrades
+    }
+}
+
+/// One upgrade's card on the chest screen, face down until revealed
+#[derive(Component)]
+pub struct ChestCard {
+    pub index: usize,
+    pub text: Entity,
+    pub name: String,
+    pub description: String,
+}
+
+/// Card popping up after being revealed
+#[derive(Component, Deref, DerefMut)]
+pub struct RevealPop(pub Timer);
+
+#[derive(Component)]
+pub struct ChestPrompt;
+
+/// Asks for the chest screen to close, ignored until every upgrade has been revealed
+#[derive(Event)]
+pub struct ChestClosed;
+
+pub struct LootPlugin;
+
+impl Plugin for LootPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<LootTable>()
+            .register_asset_loader(RonAssetLoader::<LootTable>::new(&["loot.ron"]))
+            .init_resource::<PendingChests>()
+            .add_event::<ChestClosed>()
+            .add_systems(OnExit(AppState::MainMenu), reset_pending_chests)
+            .add_systems(
+                FixedUpdate,
+                (
+                    drop_chests.after(apply_damage).before(despawn_dead_mobs),
+                    collect_chests,
+                    start_chest_opening.after(player_death),
+                )
+                    .chain()
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(OnEnter(AppState::ChestOpening), open_chest)
+            .add_systems(
+                Update,
+                (
+                    confirm_chest
+                        .run_if(resource_exists::<ActionState>())
+                        .run_if(not(resource_exists::<ReplayPlayback>())),
+                    pop_revealed_cards,
+                )
+                    .run_if(in_state(AppState::ChestOpening)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (reveal_chest_upgrades, close_chest)
+                    .chain()
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::ChestOpening)),
+            )
+            .add_systems(OnExit(AppState::ChestOpening), clear_chest_reveal);
+    }
+}
+
+pub fn reset_pending_chests(mut commands: Commands) {
+    commands.insert_resource(PendingChests::default());
+}
+
+pub fn drop_chests(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    mut rng: ResMut<GameRng>,
+    data: Res<DataAssets>,
+    loot_tables: Res<Assets<LootTable>>,
+    sprites: Res<SpriteAssets>,
+    mob_query: Query<(&Transform, &MobRank), With<Mob>>,
+) {
+    let Some(loot) = loot_tables.get(&data.loot) else {
+        return;
+    };
+
+    for event in death_events.read() {
+        let Ok((transform, rank)) = mob_query.get(event.entity) else {
+            continue;
+        };
+        let Some(chest) = loot.for_rank(*rank) else {
+            continue;
+        };
+        let rng = rng.stream("loot");
+        if !rng.gen_bool(chest.drop_chance.clamp(0.0, 1.0) as f64) {
+            continue;
+        }
+
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: chest.colour(),
+                    custom_size: Some(Vec2::splat(CHEST_SIZE)),
+                    ..default()
+                },
+                texture: sprites.square.clone(),
+                transform: Transform::from_translation(transform.translation.xy().extend(-0.4)),
+                ..default()
+            },
+            Name::new("Chest"),
+            Collider::ball(CHEST_SIZE / 2.),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            Chest {
+                upgrades: chest.roll_upgrades(rng),
+            },
+            RunEntity,
+        ));
+    }
+}
+
+pub fn collect_chests(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut pending: ResMut<PendingChests>,
+    chest_query: Query<&Chest>,
+    player_query: Query<(), With<Player>>,
+) {
+    for event in collision_events.read() {
+        if let CollisionEvent::Started(a, b, _) = event {
+            for (chest_entity, other) in [(*a, *b), (*b, *a)] {
+                if !player_query.contains(other) {
+                    continue;
+                }
+                if let Ok(chest) = chest_query.get(chest_entity) {
+                    pending.0.push_back(chest.upgrades);
+                    commands.entity(chest_entity).despawn_recursive();
+                }
+            }
+        }
+    }
+}
+
+/// Opens the next pending chest, but never over a dead player's game over
+pub fn start_chest_opening(
+    pending: Res<PendingChests>,
+    player_query: Query<&Health, With<Player>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if player_query.get_single().is_ok_and(Health::is_dead) {
+        return;
+    }
+    if !pending.0.is_empty() {
+        next_state.set(AppState::ChestOpening);
+    }
+}
+
+/// Rolls and grants the next pending chest's upgrades, then lays them out face down
+#[allow(clippy::too_many_arguments)]
+pub fn open_chest(
+    mut commands: Commands,
+    mut pending: ResMut<PendingChests>,
+    mut rng: ResMut<GameRng>,
+    data: Res<DataAssets>,
+    upgrade_catalogues: Res<Assets<UpgradeCatalogue>>,
+    weapon_catalogues: Res<Assets<WeaponCatalogue>>,
+    passive_catalogues: Res<Assets<PassiveCatalogue>>,
+    mut player_query: Query<(Entity, &mut Health, &mut Stats, &mut Inventory), With<Player>>,
+    mut weapon_query: Query<&mut Weapon>,
+) {
+    let count = pending.0.pop_front().unwrap_or(0) as usize;
+    let (Some(upgrades), Some(weapons), Some(passives), Ok(player)) = (
+        upgrade_catalogues.get(&data.upgrades),
+        weapon_catalogues.get(&data.weapons),
+        passive_catalogues.get(&data.passives),
+        player_query.get_single_mut(),
+    ) else {
+        commands.insert_resource(ChestReveal {
+            upgrades: 0,
+            revealed: 0,
+            timer: Timer::from_seconds(REVEAL_INTERVAL, TimerMode::Repeating),
+        });
+        return;
+    };
+    let (player, mut health, mut stats, mut inventory) = player;
+
+    let owned: Vec<&Weapon> = weapon_query.iter().collect();
+    let contents = roll_upgrades(
+        upgrades,
+        weapons,
+        passives,
+        &owned,
+        &inventory,
+        count,
+        rng.stream("loot"),
+    );
+    let labels: Vec<_> = contents
+        .iter()
+        .map(|upgrade| upgrade.label(weapons, passives))
+        .collect();
+    for upgrade in contents.iter() {
+        grant_upgrade(
+            &mut commands,
+            upgrade,
+            player,
+            &mut health,
+            &mut stats,
+            &mut inventory,
+            &mut weapon_query,
+            Some(weapons),
+            Some(passives),
+        );
+    }
+
+    let root = commands
+        .spawn((
+            DespawnOnExit(AppState::ChestOpening),
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.6)),
+                z_index: ZIndex::Global(i32::MAX - 1),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(24.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let title = commands
+        .spawn(TextBundle::from_section(
+            if labels.is_empty() {
+                "The chest is empty"
+            } else {
+                "Treasure!"
+            },
+            TextStyle {
+                font_size: 48.0,
+                color: Color::GOLD,
+                ..default()
+            },
+        ))
+        .id();
+    let row = commands
+        .spawn(NodeBundle {
+            style: Style {
+                column_gap: Val::Px(16.),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    let prompt = commands
+        .spawn((
+            ChestPrompt,
+            TextBundle {
+                visibility: Visibility::Hidden,
+                ..TextBundle::from_section(
+                    "Press Enter to continue",
+                    TextStyle {
+                        font_size: 18.0,
+                        color: Color::GRAY,
+                        ..default()
+                    },
+                )
+            },
+        ))
+        .id();
+    commands.entity(root).push_children(&[title, row, prompt]);
+
+    for (index, (name, description)) in labels.into_iter().enumerate() {
+        let text = commands
+            .spawn(TextBundle::from_section(
+                "?",
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::GRAY,
+                    ..default()
+                },
+            ))
+            .id();
+        let card = commands
+            .spawn((
+                ChestCard {
+                    index,
+                    text,
+                    name,
+                    description,
+                },
+                NodeBundle {
+                    background_color: BackgroundColor(CARD_HIDDEN_COLOUR),
+                    style: Style {
+                        width: Val::Px(200.),
+                        min_height: Val::Px(110.),
+                        padding: UiRect::all(Val::Px(12.)),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .id();
+        commands.entity(card).push_children(&[text]);
+        commands.entity(row).push_children(&[card]);
+    }
+
+    commands.insert_resource(ChestReveal {
+        upgrades: contents.len(),
+        revealed: 0,
+        timer: Timer::from_seconds(REVEAL_INTERVAL, TimerMode::Repeating),
+    });
+}
+
+/// Turns the cards over one at a time, showing the prompt to continue after the last
+pub fn reveal_chest_upgrades(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut reveal: ResMut<ChestReveal>,
+    mut card_query: Query<(Entity, &ChestCard, &mut BackgroundColor, &mut Transform)>,
+    mut text_query: Query<&mut Text>,
+    mut prompt_query: Query<&mut Visibility, With<ChestPrompt>>,
+) {
+    if reveal.is_finished() {
+        return;
+    }
+    for _ in 0..reveal.timer.tick(time.delta()).times_finished_this_tick() {
+        for (entity, card, mut colour, mut transform) in &mut card_query {
+            if card.index != reveal.revealed {
+                continue;
+            }
+            colour.0 = CARD_COLOUR;
+            transform.scale = Vec3::splat(REVEAL_POP_SCALE);
+            commands
+                .entity(entity)
+                .insert(RevealPop(Timer::from_seconds(REVEAL_POP, TimerMode::Once)));
+            if let Ok(mut text) = text_query.get_mut(card.text) {
+                text.sections = vec![
+                    TextSection {
+                        value: format!("{}\n", card.name),
+                        style: TextStyle {
+                            font_size: 24.0,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    },
+                    TextSection {
+                        value: card.description.clone(),
+                        style: TextStyle {
+                            font_size: 18.0,
+                            color: Color::GRAY,
+                            ..default()
+                        },
+                    },
+                ];
+            }
+        }
+        reveal.revealed += 1;
+        if reveal.is_finished() {
+            break;
+        }
+    }
+
+    if reveal.is_finished() {
+        for mut visibility in &mut prompt_query {
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
+
+/// Scales revealed cards up past full size and back, easing out
+pub fn pop_revealed_cards(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut card_query: Query<(Entity, &mut RevealPop, &mut Transform)>,
+) {
+    for (entity, mut pop, mut transform) in &mut card_query {
+        pop.tick(time.delta());
+        // Ease out back, overshooting a little before settling
+        let t = pop.percent() - 1.0;
+        let eased = 1.0 + 2.7 * t.powi(3) + 1.7 * t.powi(2);
+        transform.scale = Vec3::splat(REVEAL_POP_SCALE + (1.0 - REVEAL_POP_SCALE) * eased);
+        if pop.finished() {
+            transform.scale = Vec3::ONE;
+            commands.entity(entity).remove::<RevealPop>();
+        }
+    }
+}
+
+pub fn confirm_chest(actions: Res<ActionState>, mut closed_events: EventWriter<ChestClosed>) {
+    if actions.just_pressed(Action::Confirm) {
+        closed_events.send(ChestClosed);
+    }
+}
+
+pub fn close_chest(
+    mut closed_events: EventReader<ChestClosed>,
+    reveal: Res<ChestReveal>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let closed = closed_events.read().count() > 0;
+    if closed && reveal.is_finished() {
+        next_state.set(AppState::Playing);
+    }
+}
+
+pub fn clear_chest_reveal(mut commands: Commands) {
+    commands.remove_resource::<ChestReveal>();
+}
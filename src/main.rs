@@ -1,31 +1,41 @@
-mod animation;
-mod assets;
-mod fps;
-mod mob;
-mod physics;
-mod player;
-mod ui;
-
-mod prelude {
-    pub use crate::animation::*;
-    pub use crate::assets::*;
-    pub use crate::fps::*;
-    pub use crate::mob::*;
-    pub use crate::physics::*;
-    pub use crate::player::*;
-    pub use crate::ui::*;
-    pub use bevy::prelude::*;
-}
-
-use crate::prelude::*;
 use bevy_editor_pls::prelude::*;
+use bevy_rapier2d::render::RapierDebugRenderPlugin;
+use rust_survival::prelude::*;
+use rust_survival::{headless, replay, GamePlugins, BG_COLOUR};
 // use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
-const BG_COLOUR: Color = Color::rgb(0.06, 0.06, 0.06);
-
 fn main() {
-    App::new()
-        .insert_resource(ClearColor(BG_COLOUR))
+    let options = match LaunchOptions::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    };
+    let replay = options.replay.map(|path| match Replay::load(&path) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("{}: {error}", path.display());
+            std::process::exit(2);
+        }
+    });
+    match (options.headless, replay) {
+        (Some(_), Some(replay)) => {
+            let outcome = replay::verify(replay);
+            println!("{outcome:?}");
+            if outcome != PlaybackOutcome::Matched {
+                std::process::exit(1);
+            }
+        }
+        (Some(headless), None) => headless::run(headless),
+        (None, replay) => run_windowed(options.seed, replay),
+    }
+}
+
+fn run_windowed(seed: Option<u64>, replay: Option<Replay>) {
+    let mut app = App::new();
+    app.insert_resource(ClearColor(BG_COLOUR))
+        .insert_resource(RunSeed(seed))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Geometry Survival".to_string(),
@@ -34,21 +44,25 @@ fn main() {
             }),
             ..default()
         }))
+        .add_plugins(GamePlugins)
         .add_plugins((
             EditorPlugin::default(),
             // WorldInspectorPlugin::new()
+            RapierDebugRenderPlugin::default(),
+            MenuPlugin,
             CustomUiPlugin,
             FpsPlugin,
-            PhysicsPlugin,
             SpriteAnimationPlugin,
-            AssetsPlugin,
-            PlayerPlugin,
-            MobPlugin,
+            InputMapPlugin,
         ))
         .add_systems(Startup, setup)
-        .add_systems(Update, follow_player)
-        .add_systems(Update, bevy::window::close_on_esc)
-        .run();
+        .add_systems(Update, follow_player);
+    // Played back replays stand in for the keyboard, everything else is recorded
+    match replay {
+        Some(replay) => app.add_plugins(ReplayPlaybackPlugin(replay)),
+        None => app.add_plugins((ActionInputPlugin, ReplayRecorderPlugin)),
+    };
+    app.run();
 }
 
 fn setup(mut commands: Commands) {
@@ -0,0 +1,459 @@
+use crate::prelude::*;
+
+use bevy::app::AppExit;
+
+const TITLE: &str = "Geometry Survival";
+const BUTTON_COLOUR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_HOVER_COLOUR: Color = Color::rgb(0.25, 0.25, 0.25);
+const LOADING_BAR_WIDTH: f32 = 480.0;
+const LOADING_BAR_COLOUR: Color = Color::CYAN;
+const LOAD_ERROR_COLOUR: Color = Color::rgb(0.9, 0.3, 0.3);
+
+#[derive(Component)]
+pub struct LoadingBarFill;
+
+/// Count of loaded assets, replaced by the list of failures if any fail
+#[derive(Component)]
+pub struct LoadingText;
+
+/// Button that moves the app to the given state when pressed
+#[derive(Component)]
+pub struct MenuButton(pub AppState);
+
+/// Controls screen, opened over the main menu since leaving the menu starts a run
+#[derive(Component)]
+pub struct ControlsScreen;
+
+#[derive(Component, Clone, Copy)]
+pub enum ControlsButton {
+    Open,
+    Rebind(Control),
+    ResetDefaults,
+    Close,
+}
+
+/// Text showing what a control is bound to
+#[derive(Component)]
+pub struct BindingText(pub Control);
+
+/// Control waiting for the next key or gamepad button press to bind to it
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<Control>);
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rebinding>()
+            .add_systems(OnEnter(AppState::Loading), spawn_loading_screen)
+            .add_systems(
+                Update,
+                update_loading_screen
+                    .after(track_loading)
+                    .run_if(in_state(AppState::Loading)),
+            )
+            .add_systems(OnEnter(AppState::MainMenu), spawn_main_menu)
+            .add_systems(OnEnter(AppState::Paused), spawn_pause_screen)
+            .add_systems(OnEnter(AppState::GameOver), spawn_game_over_screen)
+            .add_systems(Update, menu_buttons)
+            .add_systems(
+                Update,
+                start_run
+                    .run_if(in_state(AppState::MainMenu))
+                    .run_if(not(any_with_component::<ControlsScreen>())),
+            )
+            .add_systems(
+                Update,
+                (controls_buttons, capture_binding, update_binding_texts)
+                    .chain()
+                    .run_if(in_state(AppState::MainMenu)),
+            )
+            .add_systems(OnExit(AppState::MainMenu), stop_rebinding)
+            .add_systems(
+                Update,
+                toggle_pause
+                    .run_if(in_state(AppState::Playing).or_else(in_state(AppState::Paused)))
+                    .run_if(not(resource_exists::<ReplayPlayback>())),
+            )
+            .add_systems(Update, leave_game_over.run_if(in_state(AppState::GameOver)))
+            // Keys pressed while rebinding are bindings, not requests to quit
+            .add_systems(
+                Update,
+                quit_game.run_if(not(any_with_component::<ControlsScreen>())),
+            );
+    }
+}
+
+fn spawn_screen(commands: &mut Commands, state: AppState, background: Color) -> Entity {
+    commands
+        .spawn((
+            DespawnOnExit(state),
+            NodeBundle {
+                background_color: BackgroundColor(background),
+                z_index: ZIndex::Global(i32::MAX - 1),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(24.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id()
+}
+
+fn spawn_text(commands: &mut Commands, parent: Entity, value: &str, font_size: f32, color: Color) {
+    let text = commands
+        .spawn(TextBundle::from_section(
+            value,
+            TextStyle {
+                font_size,
+                color,
+                ..default()
+            },
+        ))
+        .id();
+    commands.entity(parent).push_children(&[text]);
+}
+
+fn spawn_button(commands: &mut Commands, parent: Entity, label: &str, action: impl Bundle) {
+    let button = commands
+        .spawn((
+            action,
+            ButtonBundle {
+                background_color: BackgroundColor(BUTTON_COLOUR),
+                style: Style {
+                    width: Val::Px(240.),
+                    padding: UiRect::all(Val::Px(12.)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    spawn_text(commands, button, label, 28.0, Color::WHITE);
+    commands.entity(parent).push_children(&[button]);
+}
+
+pub fn spawn_loading_screen(mut commands: Commands) {
+    let root = spawn_screen(&mut commands, AppState::Loading, crate::BG_COLOUR);
+    spawn_text(&mut commands, root, "Loading...", 32.0, Color::WHITE);
+    let bar = commands
+        .spawn(NodeBundle {
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            style: Style {
+                width: Val::Px(LOADING_BAR_WIDTH),
+                height: Val::Px(12.),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    let fill = commands
+        .spawn((
+            LoadingBarFill,
+            NodeBundle {
+                background_color: BackgroundColor(LOADING_BAR_COLOUR),
+                style: Style {
+                    width: Val::Percent(0.),
+                    height: Val::Percent(100.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let text = commands
+        .spawn((
+            LoadingText,
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 18.0,
+                    color: Color::GRAY,
+                    ..default()
+                },
+            ),
+        ))
+        .id();
+    commands.entity(bar).push_children(&[fill]);
+    commands.entity(root).push_children(&[bar, text]);
+}
+
+pub fn update_loading_screen(
+    progress: Res<LoadingProgress>,
+    mut fill_query: Query<&mut Style, With<LoadingBarFill>>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+) {
+    if !progress.is_changed() {
+        return;
+    }
+    if let Ok(mut style) = fill_query.get_single_mut() {
+        style.width = Val::Percent(progress.fraction() * 100.);
+    }
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+    let section = &mut text.sections[0];
+    if progress.has_failed() {
+        let lines: Vec<String> = progress
+            .failures
+            .iter()
+            .map(|(path, failure)| format!("{path}: {failure}"))
+            .collect();
+        section.value = format!("Could not load:\n{}", lines.join("\n"));
+        section.style.color = LOAD_ERROR_COLOUR;
+    } else {
+        section.value = format!("{} / {}", progress.loaded, progress.total);
+    }
+}
+
+pub fn spawn_main_menu(mut commands: Commands) {
+    let root = spawn_screen(&mut commands, AppState::MainMenu, crate::BG_COLOUR);
+    spawn_text(&mut commands, root, TITLE, 64.0, Color::YELLOW);
+    spawn_button(&mut commands, root, "Play", MenuButton(AppState::Playing));
+    spawn_button(&mut commands, root, "Controls", ControlsButton::Open);
+    spawn_text(
+        &mut commands,
+        root,
+        "Press Enter to start",
+        18.0,
+        Color::GRAY,
+    );
+}
+
+pub fn spawn_pause_screen(mut commands: Commands) {
+    let root = spawn_screen(&mut commands, AppState::Paused, Color::BLACK.with_a(0.6));
+    spawn_text(&mut commands, root, "Paused", 48.0, Color::WHITE);
+    spawn_button(&mut commands, root, "Resume", MenuButton(AppState::Playing));
+    spawn_text(&mut commands, root, "Press P to resume", 18.0, Color::GRAY);
+}
+
+pub fn spawn_game_over_screen(
+    mut commands: Commands,
+    rng: Res<GameRng>,
+    player_query: Query<&Experience, With<Player>>,
+) {
+    let root = spawn_screen(&mut commands, AppState::GameOver, Color::BLACK.with_a(0.7));
+    spawn_text(&mut commands, root, "Game Over", 64.0, Color::RED);
+    if let Ok(experience) = player_query.get_single() {
+        let summary = format!("Reached level {}", experience.level);
+        spawn_text(&mut commands, root, &summary, 24.0, Color::WHITE);
+    }
+    let seed = format!("Seed {}", rng.seed());
+    spawn_text(&mut commands, root, &seed, 18.0, Color::GRAY);
+    spawn_button(
+        &mut commands,
+        root,
+        "Main Menu",
+        MenuButton(AppState::MainMenu),
+    );
+    spawn_text(
+        &mut commands,
+        root,
+        "Press Enter to continue",
+        18.0,
+        Color::GRAY,
+    );
+}
+
+pub fn menu_buttons(
+    mut button_query: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, button, mut colour) in &mut button_query {
+        match interaction {
+            Interaction::Pressed => next_state.set(button.0),
+            Interaction::Hovered => colour.0 = BUTTON_HOVER_COLOUR,
+            Interaction::None => colour.0 = BUTTON_COLOUR,
+        }
+    }
+}
+
+pub fn start_run(actions: Res<ActionState>, mut next_state: ResMut<NextState<AppState>>) {
+    if actions.just_pressed(Action::Confirm) {
+        next_state.set(AppState::Playing);
+    }
+}
+
+pub fn toggle_pause(
+    actions: Res<ActionState>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if actions.just_pressed(Action::Pause) {
+        next_state.set(match state.get() {
+            AppState::Paused => AppState::Playing,
+            _ => AppState::Paused,
+        });
+    }
+}
+
+pub fn leave_game_over(actions: Res<ActionState>, mut next_state: ResMut<NextState<AppState>>) {
+    if actions.just_pressed(Action::Confirm) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+pub fn quit_game(actions: Res<ActionState>, mut exit_events: EventWriter<AppExit>) {
+    if actions.just_pressed(Action::Quit) {
+        exit_events.send(AppExit);
+    }
+}
+
+fn spawn_controls_screen(commands: &mut Commands, input_map: &InputMap) {
+    let root = spawn_screen(commands, AppState::MainMenu, crate::BG_COLOUR);
+    commands
+        .entity(root)
+        .insert((ControlsScreen, ZIndex::Global(i32::MAX)));
+    spawn_text(commands, root, "Controls", 48.0, Color::WHITE);
+
+    for control in CONTROLS {
+        let row = commands
+            .spawn(NodeBundle {
+                style: Style {
+                    width: Val::Px(720.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::SpaceBetween,
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
+        spawn_text(commands, row, control.label(), 24.0, Color::WHITE);
+        let button = commands
+            .spawn((
+                ControlsButton::Rebind(control),
+                ButtonBundle {
+                    background_color: BackgroundColor(BUTTON_COLOUR),
+                    style: Style {
+                        width: Val::Px(420.),
+                        padding: UiRect::all(Val::Px(8.)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .with_children(|button| {
+                button.spawn((
+                    BindingText(control),
+                    TextBundle::from_section(
+                        input_map.describe(control),
+                        TextStyle {
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    ),
+                ));
+            })
+            .id();
+        commands.entity(row).push_children(&[button]);
+        commands.entity(root).push_children(&[row]);
+    }
+
+    spawn_button(commands, root, "Reset", ControlsButton::ResetDefaults);
+    spawn_button(commands, root, "Back", ControlsButton::Close);
+    spawn_text(
+        commands,
+        root,
+        "Click a control, then press a key or gamepad button. Backspace cancels.",
+        18.0,
+        Color::GRAY,
+    );
+}
+
+pub fn controls_buttons(
+    mut commands: Commands,
+    mut button_query: Query<
+        (&Interaction, &ControlsButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    screen_query: Query<Entity, With<ControlsScreen>>,
+    mut input_map: ResMut<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    for (interaction, button, mut colour) in &mut button_query {
+        match interaction {
+            Interaction::Pressed => match *button {
+                ControlsButton::Open => spawn_controls_screen(&mut commands, &input_map),
+                ControlsButton::Rebind(control) => rebinding.0 = Some(control),
+                ControlsButton::ResetDefaults => {
+                    *input_map = InputMap::default();
+                    save_input_map(&input_map);
+                    rebinding.0 = None;
+                }
+                ControlsButton::Close => {
+                    for screen in &screen_query {
+                        commands.entity(screen).despawn_recursive();
+                    }
+                    rebinding.0 = None;
+                }
+            },
+            Interaction::Hovered => colour.0 = BUTTON_HOVER_COLOUR,
+            Interaction::None => colour.0 = BUTTON_COLOUR,
+        }
+    }
+}
+
+/// Binds the control being rebound to the next key or gamepad button pressed
+pub fn capture_binding(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut input_map: ResMut<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let Some(control) = rebinding.0 else {
+        return;
+    };
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        rebinding.0 = None;
+        return;
+    }
+    let binding = keyboard_input
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Button(button.button_type))
+        });
+    if let Some(binding) = binding {
+        input_map.rebind(control, binding);
+        save_input_map(&input_map);
+        rebinding.0 = None;
+    }
+}
+
+pub fn update_binding_texts(
+    input_map: Res<InputMap>,
+    rebinding: Res<Rebinding>,
+    mut text_query: Query<(Ref<BindingText>, &mut Text)>,
+) {
+    for (binding_text, mut text) in &mut text_query {
+        if !input_map.is_changed() && !rebinding.is_changed() && !binding_text.is_added() {
+            continue;
+        }
+        text.sections[0].value = if rebinding.0 == Some(binding_text.0) {
+            "Press a key or button...".to_string()
+        } else {
+            input_map.describe(binding_text.0)
+        };
+    }
+}
+
+pub fn stop_rebinding(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
@@ -1,15 +1,107 @@
 use crate::prelude::*;
 use bevy_rapier2d::prelude::*;
 
+use bevy::reflect::TypePath;
 use rand::Rng;
-use std::time::Duration;
+use serde::Deserialize;
 
-const MOB_COLOUR: Color = Color::GREEN;
-const MOB_SPEED: f32 = 150.0;
-const MOB_SIZE: f32 = 64.0;
 const SPAWN_RADIUS: f32 = 1000.0;
 const MAX_TARGET_DISTANCE: f32 = 500.0;
 const RESET_DISTANCE: f32 = 2000.0;
+const BOSS_SIZE_SCALE: f32 = 3.0;
+const BOSS_HEALTH_SCALE: f32 = 40.0;
+const BOSS_XP_SCALE: u32 = 25;
+const ELITE_SIZE_SCALE: f32 = 1.6;
+const ELITE_HEALTH_SCALE: f32 = 8.0;
+const ELITE_XP_SCALE: u32 = 5;
+const MOB_ACCELERATION: f32 = 1200.0;
+const MOB_FRICTION: f32 = 1500.0;
+/// Size of a mob with a mass of one, bigger mobs are heavier by area
+const MOB_REFERENCE_SIZE: f32 = 64.0;
+
+/// Every mob kind that can be spawned, loaded from a `.mobs.ron` file
+#[derive(Asset, TypePath, Deserialize)]
+pub struct MobCatalogue {
+    pub kinds: Vec<MobKind>,
+}
+
+impl MobCatalogue {
+    pub fn get(&self, id: &str) -> Option<&MobKind> {
+        self.kinds.iter().find(|kind| kind.id == id)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct MobKind {
+    pub id: String,
+    pub name: String,
+    pub speed: f32,
+    pub health: f32,
+    pub size: f32,
+    pub contact_damage: f32,
+    pub xp: u32,
+    pub sprite: MobSprite,
+    pub colour: (f32, f32, f32),
+    #[serde(default)]
+    pub behaviour: MobBehaviour,
+    /// Phases the kind fights through when it spawns as a boss, in order
+    #[serde(default)]
+    pub boss_phases: Vec<BossPhase>,
+}
+
+impl MobKind {
+    pub fn colour(&self) -> Color {
+        Color::rgb(self.colour.0, self.colour.1, self.colour.2)
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum MobSprite {
+    Circle,
+    Square,
+    Triangle,
+    Diamond,
+}
+
+impl MobSprite {
+    pub fn texture(&self, sprites: &SpriteAssets) -> Handle<Image> {
+        match self {
+            MobSprite::Circle => sprites.circle.clone(),
+            MobSprite::Square => sprites.square.clone(),
+            MobSprite::Triangle => sprites.triangle.clone(),
+            MobSprite::Diamond => sprites.diamond.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub enum MobBehaviour {
+    /// Walks straight at the player
+    #[default]
+    Chase,
+    /// Closes to `range`, stops for `windup` seconds, then dashes along a locked direction
+    Charger {
+        range: f32,
+        windup: f32,
+        dash_speed: f32,
+        dash_duration: f32,
+        recovery: f32,
+    },
+    /// Holds at roughly `range` from the player and fires projectiles every `cooldown` seconds
+    Ranged {
+        range: f32,
+        cooldown: f32,
+        projectile_speed: f32,
+        projectile_damage: f32,
+        /// Effect each projectile puts on the player
+        #[serde(default)]
+        effect: Option<StatusEffect>,
+    },
+    /// Chases, and breaks into `count` mobs of kind `into` when killed
+    Splitter { into: String, count: u32 },
+    /// Spawns as a block of `count` mobs `spacing` apart that keeps its formation
+    Swarm { count: u32, spacing: f32 },
+}
 
 #[derive(Component)]
 pub struct Mob;
@@ -17,11 +109,33 @@ pub struct Mob;
 #[derive(Component)]
 pub struct NearestMob;
 
-#[derive(Resource)]
-pub struct MobTimer(pub Timer);
+/// Tougher than a normal mob, drops a chest
+#[derive(Component)]
+pub struct Elite;
 
-#[derive(Resource)]
-pub struct TargettingTimer(pub Timer);
+#[derive(Component)]
+pub struct Boss;
+
+/// How much tougher than its kind's base stats a mob spawns
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum MobRank {
+    #[default]
+    Normal,
+    Elite,
+    Boss,
+}
+
+/// Mob that runs straight at the player
+#[derive(Component)]
+pub struct Chase;
+
+/// Asks for a mob of the given kind to be spawned, around the player unless a position is given
+#[derive(Event)]
+pub struct SpawnMob {
+    pub kind: String,
+    pub rank: MobRank,
+    pub position: Option<Vec2>,
+}
 
 #[derive(Resource)]
 pub struct MobCount(pub u32);
@@ -30,115 +144,287 @@ pub struct MobPlugin;
 
 impl Plugin for MobPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup)
-            .add_systems(Update, spawn_mobs)
-            .add_systems(Update, mob_movement)
-            .add_systems(Update, target_nearest_mob);
+        app.add_plugins(SpatialGridPlugin::<Mob>::new(DEFAULT_CELL_SIZE))
+            .init_asset::<MobCatalogue>()
+            .register_asset_loader(RonAssetLoader::<MobCatalogue>::new(&["mobs.ron"]))
+            .add_event::<SpawnMob>()
+            .add_systems(Startup, reset_mob_count)
+            .add_systems(OnExit(AppState::MainMenu), reset_mob_count)
+            .add_systems(
+                FixedUpdate,
+                (
+                    count_mobs,
+                    spawn_mobs,
+                    mob_movement.before(apply_movement),
+                    recall_distant_mobs.run_if(
+                        in_state(AppState::Playing).and_then(fixed_cadence("mob_recall", 1.0)),
+                    ),
+                    target_nearest_mob.run_if(
+                        in_state(AppState::Playing).and_then(fixed_cadence("targeting", 0.5)),
+                    ),
+                    despawn_dead_mobs.after(apply_damage),
+                )
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            );
     }
 }
 
-pub fn setup(mut commands: Commands) {
+pub fn reset_mob_count(mut commands: Commands) {
     commands.insert_resource(MobCount(0));
-    commands.insert_resource(MobTimer(Timer::from_seconds(0.3, TimerMode::Repeating)));
-    commands.insert_resource(TargettingTimer(Timer::from_seconds(
-        0.5,
-        TimerMode::Repeating,
-    )));
 }
 
+/// Keeps `MobCount` in line with the mobs alive, however they were despawned
+pub fn count_mobs(mut count: ResMut<MobCount>, mob_query: Query<(), With<Mob>>) {
+    count.0 = mob_query.iter().count() as u32;
+}
+
+fn random_spawn_point(rng: &mut impl Rng, around: Vec2) -> Vec2 {
+    let angle: f32 = rng.gen_range(0.0..360.0);
+    Vec2::new(
+        (angle.cos() * SPAWN_RADIUS) + around.x,
+        (angle.sin() * SPAWN_RADIUS) + around.y,
+    )
+}
+
+/// Spawns a single mob of `kind` with its behaviour components, swarms excepted
+pub fn spawn_mob(
+    commands: &mut Commands,
+    sprites: &SpriteAssets,
+    kind: &MobKind,
+    position: Vec2,
+    rank: MobRank,
+) -> Entity {
+    let (size, health, xp) = match rank {
+        MobRank::Normal => (kind.size, kind.health, kind.xp),
+        MobRank::Elite => (
+            kind.size * ELITE_SIZE_SCALE,
+            kind.health * ELITE_HEALTH_SCALE,
+            kind.xp * ELITE_XP_SCALE,
+        ),
+        MobRank::Boss => (
+            kind.size * BOSS_SIZE_SCALE,
+            kind.health * BOSS_HEALTH_SCALE,
+            kind.xp * BOSS_XP_SCALE,
+        ),
+    };
+
+    let mut mob = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: kind.colour(),
+                custom_size: Some(Vec2::splat(size)),
+                ..default()
+            },
+            texture: kind.sprite.texture(sprites),
+            transform: Transform::from_translation(position.extend(0.0)),
+            ..default()
+        },
+        Name::new(kind.name.clone()),
+        RigidBody::Dynamic,
+        Collider::ball((size * 0.7) / 2.),
+        CollisionGroups::new(MOB_GROUP, Group::ALL),
+        GravityScale(0.),
+        Velocity::zero(),
+        LockedAxes::ROTATION_LOCKED,
+        Health::new(health),
+        StatusEffects::default(),
+        ContactDamage(kind.contact_damage),
+        XpDrop(xp),
+        MovementController::new(kind.speed, MOB_ACCELERATION, MOB_FRICTION)
+            .with_mass((size / MOB_REFERENCE_SIZE).powi(2)),
+        Mob,
+        RunEntity,
+    ));
+
+    match &kind.behaviour {
+        MobBehaviour::Chase | MobBehaviour::Swarm { .. } => {
+            mob.insert(Chase);
+        }
+        MobBehaviour::Charger {
+            range,
+            windup,
+            dash_speed,
+            dash_duration,
+            recovery,
+        } => {
+            mob.insert(Charger::new(
+                *range,
+                *windup,
+                *dash_speed,
+                *dash_duration,
+                *recovery,
+            ));
+        }
+        MobBehaviour::Ranged {
+            range,
+            cooldown,
+            projectile_speed,
+            projectile_damage,
+            effect,
+        } => {
+            mob.insert(Ranged::new(
+                *range,
+                *cooldown,
+                *projectile_speed,
+                *projectile_damage,
+                *effect,
+            ));
+        }
+        MobBehaviour::Splitter { into, count } => {
+            mob.insert((
+                Chase,
+                Splitter {
+                    into: into.clone(),
+                    count: *count,
+                },
+            ));
+        }
+    }
+    mob.insert(rank);
+    match rank {
+        MobRank::Normal => {}
+        MobRank::Elite => {
+            mob.insert(Elite);
+        }
+        MobRank::Boss => {
+            mob.insert(Boss);
+            if !kind.boss_phases.is_empty() {
+                mob.insert(BossScript::new(kind.boss_phases.clone()));
+            }
+        }
+    }
+    let entity = mob.id();
+    mob.insert(StatusTint::new(entity, kind.colour()));
+    entity
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_mobs(
     mut commands: Commands,
-    time: Res<Time>,
-    mut timer: ResMut<MobTimer>,
-    mut count: ResMut<MobCount>,
+    mut spawn_events: EventReader<SpawnMob>,
+    mut next_swarm: Local<u32>,
+    mut rng: ResMut<GameRng>,
+    data: Res<DataAssets>,
+    catalogues: Res<Assets<MobCatalogue>>,
+    sprites: Res<SpriteAssets>,
     player_query: Query<&Transform, With<Player>>,
 ) {
-    timer.0.tick(time.delta());
-    if timer.0.just_finished() && count.0 < 3 {
-        if let Ok(player) = player_query.get_single() {
-            let angle: f32 = rand::thread_rng().gen_range(0.0..360.0);
-            let pos = Vec3::new(
-                (angle.cos() * SPAWN_RADIUS) + player.translation.x,
-                (angle.sin() * SPAWN_RADIUS) + player.translation.y,
-                0.0,
-            );
+    let (Ok(player), Some(catalogue)) = (player_query.get_single(), catalogues.get(&data.mobs))
+    else {
+        spawn_events.clear();
+        return;
+    };
+
+    for event in spawn_events.read() {
+        let Some(kind) = catalogue.get(&event.kind) else {
+            warn!("Tried to spawn unknown mob kind {}", event.kind);
+            continue;
+        };
+        let position = event
+            .position
+            .unwrap_or_else(|| random_spawn_point(rng.stream("spawning"), player.translation.xy()));
 
-            let _root = commands
-                .spawn((
-                    SpatialBundle::from_transform(Transform::from_translation(pos)),
-                    Name::new("Mob"),
-                    RigidBody::Dynamic,
-                    Collider::ball((MOB_SIZE * 0.7) / 2.),
-                    GravityScale(0.),
-                    Velocity::zero(),
-                    LockedAxes::ROTATION_LOCKED,
-                    Mob,
-                ))
-                .id();
-            count.0 += 1;
+        match kind.behaviour {
+            MobBehaviour::Swarm { count, spacing } if event.rank == MobRank::Normal => {
+                let group = *next_swarm;
+                *next_swarm = next_swarm.wrapping_add(1);
+                for offset in swarm_formation(count, spacing) {
+                    let member = spawn_mob(
+                        &mut commands,
+                        &sprites,
+                        kind,
+                        position + offset,
+                        MobRank::Normal,
+                    );
+                    commands
+                        .entity(member)
+                        .remove::<Chase>()
+                        .insert(SwarmMember { group, offset });
+                }
+            }
+            _ => {
+                spawn_mob(&mut commands, &sprites, kind, position, event.rank);
+            }
         }
     }
 }
 
 pub fn mob_movement(
     player_query: Query<&Transform, With<Player>>,
-    mut mob_query: Query<(&mut Velocity, &mut Transform), (With<Mob>, Without<Player>)>,
+    mut mob_query: Query<(&mut MovementController, &Transform), With<Chase>>,
 ) {
     if let Ok(player) = player_query.get_single() {
-        for (mut velocity, mut transform) in &mut mob_query {
-            let mut direction = Vec2::new(
-                player.translation.x - transform.translation.x,
-                player.translation.y - transform.translation.y,
-            );
-
-            let distance_sq = direction.length_squared();
-            if distance_sq > 0.0 {
-                direction = direction.normalize();
-            }
-
-            if distance_sq > RESET_DISTANCE.powf(2.0) {
-                let angle: f32 = rand::thread_rng().gen_range(0.0..360.0);
-                transform.translation = Vec3::new(
-                    (angle.cos() * SPAWN_RADIUS) + player.translation.x,
-                    (angle.sin() * SPAWN_RADIUS) + player.translation.y,
-                    0.0,
-                );
-            }
-
-            velocity.linvel = direction * MOB_SPEED;
+        for (mut controller, transform) in &mut mob_query {
+            controller.desired =
+                (player.translation.xy() - transform.translation.xy()).normalize_or_zero();
         }
     }
 }
 
+/// Moves mobs that fell too far behind back onto the spawn ring
+pub fn recall_distant_mobs(
+    mut rng: ResMut<GameRng>,
+    player_query: Query<&Transform, With<Player>>,
+    mut mob_query: Query<&mut Transform, (With<Mob>, Without<Player>)>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let mut distant: Vec<_> = mob_query
+        .iter_mut()
+        .filter(|transform| {
+            player.translation.distance_squared(transform.translation) > RESET_DISTANCE.powf(2.0)
+        })
+        .collect();
+    // Query order depends on what earlier runs spawned, so draw in an order that doesn't,
+    // keeping replays of later runs in a session in sync
+    distant.sort_by(|a, b| {
+        let a = a.translation.xy().to_array().map(f32::to_bits);
+        let b = b.translation.xy().to_array().map(f32::to_bits);
+        a.cmp(&b)
+    });
+    for mut transform in distant {
+        let position = random_spawn_point(rng.stream("spawning"), player.translation.xy());
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
 pub fn target_nearest_mob(
     mut commands: Commands,
-    time: Res<Time>,
-    mut timer: ResMut<MobTimer>,
+    grid: Res<SpatialGrid<Mob>>,
     player_query: Query<&Transform, With<Player>>,
-    mob_query: Query<(Entity, &Transform), With<Mob>>,
+    target_query: Query<Entity, With<NearestMob>>,
+    mob_query: Query<(), With<Mob>>,
 ) {
-    timer.0.tick(time.delta());
-    if timer.0.just_finished() {
-        if let Ok(player_transform) = player_query.get_single() {
-            let mut closest_distance = f32::MAX;
-            let mut closest_entity: Option<Entity> = None;
+    if let Ok(player_transform) = player_query.get_single() {
+        let closest = grid
+            .nearest(player_transform.translation.xy(), MAX_TARGET_DISTANCE)
+            .map(|(entity, _)| entity)
+            .filter(|entity| mob_query.contains(*entity));
 
-            for (entity, transform) in mob_query.iter() {
+        for entity in target_query.iter() {
+            if Some(entity) != closest {
                 commands.entity(entity).remove::<NearestMob>();
-                let distance = player_transform
-                    .translation
-                    .distance_squared(transform.translation);
-                if distance < closest_distance {
-                    closest_distance = distance;
-                    closest_entity = Some(entity);
-                }
             }
-
-            if closest_distance <= MAX_TARGET_DISTANCE.powf(2.) {
-                if let Some(closest) = closest_entity {
-                    commands.entity(closest).insert(NearestMob);
-                }
+        }
+        if let Some(closest) = closest {
+            if !target_query.contains(closest) {
+                commands.entity(closest).insert(NearestMob);
             }
         }
     }
 }
+
+pub fn despawn_dead_mobs(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    mob_query: Query<(), With<Mob>>,
+) {
+    for event in death_events.read() {
+        if mob_query.contains(event.entity) {
+            commands.entity(event.entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use crate::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use bevy::utils::HashMap;
+use std::f32::consts::TAU;
+
+const MOB_PROJECTILE_SIZE: f32 = 16.0;
+const MOB_PROJECTILE_LIFETIME: f32 = 4.0;
+const MOB_PROJECTILE_COLOUR: Color = Color::rgb(1.0, 0.3, 0.6);
+const MOB_PROJECTILE_KNOCKBACK: f32 = 200.0;
+/// Ranged mobs back off once the player is closer than this fraction of their range
+const RANGED_RETREAT_FRACTION: f32 = 0.7;
+/// How hard swarm members are pulled back to their slot in the formation
+const FORMATION_STIFFNESS: f32 = 4.0;
+const SPLIT_RADIUS: f32 = 24.0;
+/// Mobs closer than this push each other apart
+const SEPARATION_RADIUS: f32 = 48.0;
+const SEPARATION_NEIGHBOURS: usize = 6;
+const SEPARATION_ACCELERATION: f32 = 2400.0;
+
+pub enum ChargeState {
+    Approach,
+    WindUp(Timer),
+    Dash(Timer, Vec2),
+    Recover(Timer),
+}
+
+#[derive(Component)]
+pub struct Charger {
+    pub range: f32,
+    pub windup: f32,
+    pub dash_speed: f32,
+    pub dash_duration: f32,
+    pub recovery: f32,
+    pub state: ChargeState,
+}
+
+impl Charger {
+    pub fn new(
+        range: f32,
+        windup: f32,
+        dash_speed: f32,
+        dash_duration: f32,
+        recovery: f32,
+    ) -> Self {
+        Self {
+            range,
+            windup,
+            dash_speed,
+            dash_duration,
+            recovery,
+            state: ChargeState::Approach,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Ranged {
+    pub range: f32,
+    pub projectile_speed: f32,
+    pub projectile_damage: f32,
+    pub effect: Option<StatusEffect>,
+    pub cooldown: Timer,
+}
+
+impl Ranged {
+    pub fn new(
+        range: f32,
+        cooldown: f32,
+        projectile_speed: f32,
+        projectile_damage: f32,
+        effect: Option<StatusEffect>,
+    ) -> Self {
+        Self {
+            range,
+            projectile_speed,
+            projectile_damage,
+            effect,
+            cooldown: Timer::from_seconds(cooldown, TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Splitter {
+    pub into: String,
+    pub count: u32,
+}
+
+/// Member of a swarm, holding `offset` from the swarm's centre
+#[derive(Component)]
+pub struct SwarmMember {
+    pub group: u32,
+    pub offset: Vec2,
+}
+
+/// Shot fired by a ranged mob or a boss, hurts the player on touch
+#[derive(Component)]
+pub struct MobProjectile {
+    pub damage: f32,
+    pub effect: Option<StatusEffect>,
+}
+
+pub struct MobBehaviourPlugin;
+
+impl Plugin for MobBehaviourPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                charge_at_player,
+                ranged_attack,
+                move_swarms,
+                mob_projectile_hits.before(apply_damage),
+                split_on_death.after(apply_damage),
+                separate_mobs
+                    .after(mob_movement)
+                    .after(charge_at_player)
+                    .after(ranged_attack)
+                    .after(move_swarms)
+                    .before(apply_movement),
+            )
+                .in_set(TickSet::Gameplay)
+                .run_if(in_state(AppState::Playing)),
+        );
+    }
+}
+
+/// Offsets for `count` mobs laid out in a square block centred on the origin
+pub fn swarm_formation(count: u32, spacing: f32) -> Vec<Vec2> {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let rows = count.div_ceil(columns);
+    let centre = Vec2::new((columns - 1) as f32, rows.saturating_sub(1) as f32) / 2.0;
+    (0..count)
+        .map(|i| (Vec2::new((i % columns) as f32, (i / columns) as f32) - centre) * spacing)
+        .collect()
+}
+
+fn face(transform: &mut Transform, direction: Vec2) {
+    if direction != Vec2::ZERO {
+        transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x));
+    }
+}
+
+pub fn charge_at_player(
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    mut mob_query: Query<
+        (
+            &mut Charger,
+            &mut MovementController,
+            &mut Transform,
+            &StatusEffects,
+        ),
+        Without<Player>,
+    >,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+
+    for (mut charger, mut controller, mut transform, effects) in &mut mob_query {
+        // Stunned and frozen chargers hold whatever they were doing until it wears off
+        if !effects.can_act() {
+            continue;
+        }
+        let offset = player.translation.xy() - transform.translation.xy();
+        let direction = offset.normalize_or_zero();
+        let charger = &mut *charger;
+
+        match &mut charger.state {
+            ChargeState::Approach => {
+                face(&mut transform, direction);
+                controller.desired = direction;
+                if offset.length_squared() <= charger.range.powf(2.0) {
+                    charger.state =
+                        ChargeState::WindUp(Timer::from_seconds(charger.windup, TimerMode::Once));
+                }
+            }
+            ChargeState::WindUp(timer) => {
+                face(&mut transform, direction);
+                controller.desired = Vec2::ZERO;
+                if timer.tick(time.delta()).finished() {
+                    charger.state = ChargeState::Dash(
+                        Timer::from_seconds(charger.dash_duration, TimerMode::Once),
+                        direction,
+                    );
+                }
+            }
+            ChargeState::Dash(timer, dash_direction) => {
+                controller.drive(*dash_direction * charger.dash_speed * effects.speed_multiplier());
+                if timer.tick(time.delta()).finished() {
+                    charger.state = ChargeState::Recover(Timer::from_seconds(
+                        charger.recovery,
+                        TimerMode::Once,
+                    ));
+                }
+            }
+            ChargeState::Recover(timer) => {
+                controller.desired = Vec2::ZERO;
+                if timer.tick(time.delta()).finished() {
+                    charger.state = ChargeState::Approach;
+                }
+            }
+        }
+    }
+}
+
+pub fn ranged_attack(
+    mut commands: Commands,
+    time: Res<Time>,
+    sprites: Res<SpriteAssets>,
+    player_query: Query<&Transform, With<Player>>,
+    mut mob_query: Query<
+        (
+            &mut Ranged,
+            &mut MovementController,
+            &Transform,
+            &StatusEffects,
+        ),
+        Without<Player>,
+    >,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+
+    for (mut ranged, mut controller, transform, effects) in &mut mob_query {
+        let offset = player.translation.xy() - transform.translation.xy();
+        let distance = offset.length();
+        let direction = offset.normalize_or_zero();
+
+        controller.desired = if distance > ranged.range {
+            direction
+        } else if distance < ranged.range * RANGED_RETREAT_FRACTION {
+            -direction
+        } else {
+            Vec2::ZERO
+        };
+
+        if !effects.can_act() {
+            continue;
+        }
+        ranged.cooldown.tick(time.delta());
+        if ranged.cooldown.just_finished() && distance <= ranged.range {
+            spawn_mob_projectile(
+                &mut commands,
+                &sprites,
+                transform.translation.xy(),
+                direction * ranged.projectile_speed,
+                ranged.projectile_damage,
+                ranged.effect,
+            );
+        }
+    }
+}
+
+/// Spawns a shot that flies at `velocity` and hurts the player on touch
+pub fn spawn_mob_projectile(
+    commands: &mut Commands,
+    sprites: &SpriteAssets,
+    position: Vec2,
+    velocity: Vec2,
+    damage: f32,
+    effect: Option<StatusEffect>,
+) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: MOB_PROJECTILE_COLOUR,
+                custom_size: Some(Vec2::splat(MOB_PROJECTILE_SIZE)),
+                ..default()
+            },
+            texture: sprites.circle.clone(),
+            transform: Transform::from_translation(position.extend(0.5)),
+            ..default()
+        },
+        Name::new("MobProjectile"),
+        RigidBody::KinematicVelocityBased,
+        Collider::ball(MOB_PROJECTILE_SIZE / 2.),
+        Sensor,
+        ActiveEvents::COLLISION_EVENTS,
+        Velocity::linear(velocity),
+        MobProjectile { damage, effect },
+        Lifetime(Timer::from_seconds(
+            MOB_PROJECTILE_LIFETIME,
+            TimerMode::Once,
+        )),
+        RunEntity,
+    ));
+}
+
+pub fn mob_projectile_hits(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut status_events: EventWriter<ApplyStatus>,
+    projectile_query: Query<(&MobProjectile, &Velocity)>,
+    player_query: Query<(), (With<Player>, Without<Invulnerable>)>,
+) {
+    for event in collision_events.read() {
+        if let CollisionEvent::Started(a, b, _) = event {
+            for (projectile_entity, target) in [(*a, *b), (*b, *a)] {
+                if !player_query.contains(target) {
+                    continue;
+                }
+                if let Ok((projectile, velocity)) = projectile_query.get(projectile_entity) {
+                    damage_events.send(DamageEvent {
+                        target,
+                        amount: projectile.damage,
+                        knockback: velocity.linvel.normalize_or_zero() * MOB_PROJECTILE_KNOCKBACK,
+                        over_time: false,
+                    });
+                    if let Some(effect) = projectile.effect {
+                        status_events.send(ApplyStatus { target, effect });
+                    }
+                    commands.entity(projectile_entity).despawn_recursive();
+                }
+            }
+        }
+    }
+}
+
+/// Moves every swarm towards the player as one block, pulling stragglers back into formation
+pub fn move_swarms(
+    player_query: Query<&Transform, With<Player>>,
+    mut mob_query: Query<(&SwarmMember, &mut MovementController, &Transform), Without<Player>>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+
+    let mut centres: HashMap<u32, (Vec2, f32)> = HashMap::new();
+    for (member, _, transform) in mob_query.iter() {
+        let centre = centres.entry(member.group).or_insert((Vec2::ZERO, 0.0));
+        centre.0 += transform.translation.xy() - member.offset;
+        centre.1 += 1.0;
+    }
+
+    for (member, mut controller, transform) in &mut mob_query {
+        let (sum, count) = centres[&member.group];
+        let centre = sum / count;
+        let direction = (player.translation.xy() - centre).normalize_or_zero();
+        let slot = centre + member.offset;
+        let correction = (slot - transform.translation.xy()) * FORMATION_STIFFNESS;
+        controller.desired = direction + correction / controller.max_speed;
+    }
+}
+
+pub fn split_on_death(
+    mut death_events: EventReader<DeathEvent>,
+    mut spawn_events: EventWriter<SpawnMob>,
+    splitter_query: Query<(&Splitter, &Transform)>,
+) {
+    for event in death_events.read() {
+        if let Ok((splitter, transform)) = splitter_query.get(event.entity) {
+            for i in 0..splitter.count {
+                let angle = TAU * i as f32 / splitter.count as f32;
+                spawn_events.send(SpawnMob {
+                    kind: splitter.into.clone(),
+                    rank: MobRank::Normal,
+                    position: Some(
+                        transform.translation.xy() + Vec2::from_angle(angle) * SPLIT_RADIUS,
+                    ),
+                });
+            }
+        }
+    }
+}
+
+/// Pushes mobs away from their closest neighbours so crowds spread out instead of stacking
+pub fn separate_mobs(
+    time: Res<Time>,
+    grid: Res<SpatialGrid<Mob>>,
+    mut mob_query: Query<(Entity, &mut MovementController, &Transform), With<Mob>>,
+) {
+    for (entity, mut controller, transform) in &mut mob_query {
+        let position = transform.translation.xy();
+        let mut push = Vec2::ZERO;
+        for (other, other_position) in
+            grid.k_nearest(position, SEPARATION_NEIGHBOURS + 1, SEPARATION_RADIUS)
+        {
+            let offset = position - other_position;
+            let distance = offset.length();
+            if other == entity || distance <= f32::EPSILON {
+                continue;
+            }
+            push += offset / distance * (1.0 - distance / SEPARATION_RADIUS);
+        }
+        controller.apply_impulse(push * SEPARATION_ACCELERATION * time.delta_seconds());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+/// Steers a body's velocity towards where it wants to go instead of setting it outright, so
+/// collisions, knockback and pushes carry over from frame to frame
+#[derive(Component)]
+pub struct MovementController {
+    pub max_speed: f32,
+    /// Rate the velocity turns towards `desired`, in units per second squared
+    pub acceleration: f32,
+    /// Rate the velocity falls off with no movement asked for
+    pub friction: f32,
+    /// Scales incoming impulses down, so heavy bodies barely flinch
+    pub mass: f32,
+    /// Scales `max_speed` for slows and hastes
+    pub speed_multiplier: f32,
+    /// Direction to move in this frame, shorter than unit length to move slower
+    pub desired: Vec2,
+    /// Velocity to hold this frame regardless of acceleration, for dashes and lunges
+    driven: Option<Vec2>,
+    impulse: Vec2,
+}
+
+impl MovementController {
+    pub fn new(max_speed: f32, acceleration: f32, friction: f32) -> Self {
+        Self {
+            max_speed,
+            acceleration,
+            friction,
+            mass: 1.0,
+            speed_multiplier: 1.0,
+            desired: Vec2::ZERO,
+            driven: None,
+            impulse: Vec2::ZERO,
+        }
+    }
+
+    pub fn with_mass(mut self, mass: f32) -> Self {
+        self.mass = mass;
+        self
+    }
+
+    /// Queues a sudden push, applied on the next movement step
+    pub fn apply_impulse(&mut self, impulse: Vec2) {
+        self.impulse += impulse / self.mass.max(f32::EPSILON);
+    }
+
+    /// Moves at exactly `velocity` this frame. The body slows back down to its normal speed at its
+    /// own acceleration once this stops being called.
+    pub fn drive(&mut self, velocity: Vec2) {
+        self.driven = Some(velocity);
+    }
+
+    /// Velocity the controller steers towards when not driven
+    pub fn target_velocity(&self) -> Vec2 {
+        self.desired.clamp_length_max(1.0) * self.max_speed * self.speed_multiplier
+    }
+}
+
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            apply_movement
+                .in_set(TickSet::Gameplay)
+                .run_if(in_state(AppState::Playing)),
+        );
+    }
+}
+
+/// Writes the controllers into rapier velocities. Anything setting `desired`, driving or pushing a
+/// body runs before this.
+pub fn apply_movement(time: Res<Time>, mut query: Query<(&mut MovementController, &mut Velocity)>) {
+    let delta = time.delta_seconds();
+    for (mut controller, mut velocity) in &mut query {
+        let velocity_now = match controller.driven.take() {
+            Some(driven) => driven,
+            None => {
+                let target = controller.target_velocity();
+                let rate = if controller.desired == Vec2::ZERO {
+                    controller.friction
+                } else {
+                    controller.acceleration
+                };
+                move_towards(velocity.linvel, target, rate * delta)
+            }
+        };
+        velocity.linvel = velocity_now + std::mem::take(&mut controller.impulse);
+    }
+}
+
+fn move_towards(current: Vec2, target: Vec2, max_step: f32) -> Vec2 {
+    let offset = target - current;
+    let distance = offset.length();
+    if distance <= max_step {
+        target
+    } else {
+        current + offset / distance * max_step
+    }
+}
@@ -1,12 +1,91 @@
 use crate::prelude::*;
 
 use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::prelude::{
+    BroadPhase, CCDSolver, ColliderSet, ImpulseJointSet, IslandManager, MultibodyJointSet,
+    NarrowPhase, PhysicsPipeline, QueryPipeline, RigidBodySet,
+};
+use std::time::Duration;
+
+/// Seconds each fixed tick of gameplay advances by, so a run plays out the same however fast
+/// it's simulated
+pub const TIMESTEP: f32 = 1.0 / 60.0;
+
+pub const PLAYER_GROUP: Group = Group::GROUP_1;
+pub const MOB_GROUP: Group = Group::GROUP_2;
+
+/// Stages of one fixed tick, run in this order in `FixedUpdate`
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TickSet {
+    /// Replayed input for the tick
+    Input,
+    /// Applies any state change asked for since the last tick, so every tick runs in one state
+    Transition,
+    /// Rebuilds whatever gameplay reads from the last tick's results
+    Prepare,
+    Gameplay,
+    /// After physics has stepped, for anything that needs the tick's final state
+    Record,
+}
 
 pub struct PhysicsPlugin;
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
-            .add_plugins(RapierDebugRenderPlugin::default());
+        // Inserted before the rapier plugin, which keeps an existing configuration
+        app.insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: TIMESTEP,
+                substeps: 1,
+            },
+            ..default()
+        })
+        .insert_resource(Time::<Fixed>::from_duration(Duration::from_secs_f32(
+            TIMESTEP,
+        )))
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0).in_fixed_schedule())
+        .configure_sets(
+            FixedUpdate,
+            (
+                TickSet::Input,
+                TickSet::Transition,
+                TickSet::Prepare,
+                TickSet::Gameplay.before(PhysicsSet::SyncBackend),
+                TickSet::Record.after(PhysicsSet::Writeback),
+            )
+                .chain(),
+        )
+        .add_systems(
+            FixedUpdate,
+            apply_state_transition::<AppState>.in_set(TickSet::Transition),
+        )
+        .add_systems(OnExit(AppState::MainMenu), reset_physics)
+        .add_systems(OnEnter(AppState::Playing), resume_physics)
+        .add_systems(OnExit(AppState::Playing), pause_physics);
     }
 }
+
+/// Starts each run from an empty physics world, so leftover state from the last run can't
+/// change how this one plays out. The context is emptied in place rather than replaced, since a
+/// new one would lose the pixels per meter scale the plugin set up.
+pub fn reset_physics(mut context: ResMut<RapierContext>) {
+    let context = context.as_mut();
+    context.islands = IslandManager::new();
+    context.broad_phase = BroadPhase::new();
+    context.narrow_phase = NarrowPhase::new();
+    context.bodies = RigidBodySet::new();
+    context.colliders = ColliderSet::new();
+    context.impulse_joints = ImpulseJointSet::new();
+    context.multibody_joints = MultibodyJointSet::new();
+    context.ccd_solver = CCDSolver::new();
+    context.pipeline = PhysicsPipeline::new();
+    context.query_pipeline = QueryPipeline::new();
+}
+
+pub fn pause_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = false;
+}
+
+pub fn resume_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = true;
+}
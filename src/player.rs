@@ -1,11 +1,19 @@
 use crate::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use std::time::Duration;
-
 const PLAYER_COLOUR: Color = Color::PURPLE;
 const PLAYER_SPEED: f32 = 500.0;
+const PLAYER_ACCELERATION: f32 = 4000.0;
+const PLAYER_FRICTION: f32 = 5000.0;
 const PLAYER_SIZE: f32 = 64.0;
+const PLAYER_HEALTH: f32 = 100.0;
+const PLAYER_INVULNERABILITY: f32 = 0.5;
+const PLAYER_MAGNET_RADIUS: f32 = 120.0;
+const WEAPON_SLOTS: usize = 6;
+const PASSIVE_SLOTS: usize = 6;
+const DASH_SPEED: f32 = 1500.0;
+const DASH_DURATION: f32 = 0.2;
+const DASH_COOLDOWN: f32 = 1.5;
 
 #[derive(Component)]
 pub struct Player;
@@ -13,156 +21,147 @@ pub struct Player;
 #[derive(Component)]
 pub struct PlayerEyes;
 
-#[derive(Resource)]
-pub struct PlayerAnimations {
-    pub idle_animation: Handle<AnimationClip>,
-    pub walk_animation: Handle<AnimationClip>,
+/// Holds the `AnimationPlayer` moving the player's body and eyes
+#[derive(Component)]
+pub struct PlayerAnchor;
+
+/// Lets the player burst forward, passing through mobs without taking damage
+#[derive(Component)]
+pub struct Dash {
+    pub cooldown: Timer,
+    /// Time left of the dash in progress
+    active: Option<Timer>,
+    direction: Vec2,
+    /// Direction last moved in, dashed towards when standing still
+    facing: Vec2,
+}
+
+impl Dash {
+    pub fn new(cooldown: f32) -> Self {
+        let mut cooldown = Timer::from_seconds(cooldown, TimerMode::Once);
+        // Ready from the start of the run
+        cooldown.tick(cooldown.duration());
+        Self {
+            cooldown,
+            active: None,
+            direction: Vec2::ZERO,
+            facing: Vec2::X,
+        }
+    }
+
+    pub fn is_dashing(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+asset_collection! {
+    /// Transform clips for the player's body and eyes
+    pub struct PlayerAnimations {
+        pub idle_animation: AnimationClip = "animations/player/idle.anim.ron",
+        pub walk_animation: AnimationClip = "animations/player/walk.anim.ron",
+        pub dash_animation: AnimationClip = "animations/player/dash.anim.ron",
+        pub hurt_animation: AnimationClip = "animations/player/hurt.anim.ron",
+        pub hit_animation: AnimationClip = "animations/player/hit.anim.ron",
+    }
 }
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_player).add_systems(
-            Update,
-            (player_movement, target_nearest_mob, lookat_nearest_target),
-        );
+        app.init_resource::<PlayerInput>()
+            .load_collection::<PlayerAnimations>()
+            .add_systems(OnExit(AppState::MainMenu), spawn_player)
+            .add_systems(
+                FixedUpdate,
+                (
+                    update_dash.before(player_movement),
+                    player_movement.before(apply_movement),
+                    player_death.after(apply_damage),
+                )
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    lookat_nearest_target,
+                    blink_eyes.before(run_animation_state_machines),
+                    update_player_animation.before(run_animation_state_machines),
+                )
+                    .run_if(in_state(AppState::Playing)),
+            );
     }
 }
 
 pub fn spawn_player(
     mut commands: Commands,
-    mut animations: ResMut<Assets<AnimationClip>>,
+    sheets: Res<Assets<SpriteSheet>>,
     handles: Res<SpriteAssets>,
+    animations: Res<PlayerAnimations>,
 ) {
     let animation_player = AnimationPlayer::default();
+    // The clips in `animations/player` find their targets by these names
     let body_sprite_name = Name::new("BodySprite");
     let eye_sprite_name = Name::new("EyeSprite");
     let anchor_name = Name::new("PlayerAnchor");
     let eye_anchor_name = Name::new("EyeAnchor");
 
-    let mut idle_animation = AnimationClip::default();
-    idle_animation.add_curve_to_path(
-        EntityPath {
-            parts: vec![anchor_name.clone(), body_sprite_name.clone()],
-        },
-        VariableCurve {
-            keyframe_timestamps: vec![0.0, 0.5, 1.0],
-            keyframes: Keyframes::Scale(vec![
-                Vec3::new(1., 1., 1.),
-                Vec3::new(1.05, 0.95, 1.),
-                Vec3::new(1., 1., 1.),
-            ]),
-        },
-    );
-    idle_animation.add_curve_to_path(
-        EntityPath {
-            parts: vec![anchor_name.clone(), eye_anchor_name.clone()],
-        },
-        VariableCurve {
-            keyframe_timestamps: vec![0.0, 0.5, 1.0],
-            keyframes: Keyframes::Translation(vec![
-                Vec3::new(0., 0., 0.),
-                Vec3::new(0., 3., 0.),
-                Vec3::new(0., 0., 0.),
-            ]),
-        },
-    );
-    idle_animation.add_curve_to_path(
-        EntityPath {
-            parts: vec![anchor_name.clone()],
-        },
-        VariableCurve {
-            keyframe_timestamps: vec![0.0],
-            keyframes: Keyframes::Translation(vec![Vec3::new(0., 0., 0.)]),
-        },
-    );
-
-    let mut walk_animation = AnimationClip::default();
-    walk_animation.add_curve_to_path(
-        EntityPath {
-            parts: vec![anchor_name.clone(), body_sprite_name.clone()],
-        },
-        VariableCurve {
-            keyframe_timestamps: vec![0.1, 0.3, 0.5],
-            keyframes: Keyframes::Scale(vec![
-                Vec3::new(0.95, 1.1, 1.),
-                Vec3::new(1.1, 0.95, 1.),
-                Vec3::new(0.95, 1.1, 1.),
-            ]),
-        },
-    );
-
-    // 0, 8, 0, -8
-    walk_animation.add_curve_to_path(
-        EntityPath {
-            parts: vec![anchor_name.clone()],
-        },
-        VariableCurve {
-            keyframe_timestamps: vec![0.0, 0.1, 0.2, 0.3, 0.4],
-            keyframes: Keyframes::Translation(vec![
-                Vec3::new(0., 0., 0.),
-                Vec3::new(0., 8., 0.),
-                Vec3::new(0., 0., 0.),
-                Vec3::new(0., -8., 0.),
-                Vec3::new(0., 0., 0.),
-            ]),
-        },
-    );
-
-    walk_animation.add_curve_to_path(
-        EntityPath {
-            parts: vec![anchor_name.clone(), eye_anchor_name.clone()],
-        },
-        VariableCurve {
-            keyframe_timestamps: vec![0.1, 0.3, 0.5],
-            keyframes: Keyframes::Translation(vec![
-                Vec3::new(0., 2., 0.),
-                Vec3::new(0., -3., 0.),
-                Vec3::new(0., 2., 0.),
-            ]),
-        },
-    );
-
-    let mut blink_animation = AnimationClip::default();
-    blink_animation.add_curve_to_path(
-        EntityPath {
-            parts: vec![eye_sprite_name.clone()],
-        },
-        VariableCurve {
-            keyframe_timestamps: vec![0.1, 0.3, 0.5],
-            keyframes: Keyframes::Scale(vec![
-                Vec3::new(0.95, 1.1, 1.),
-                Vec3::new(1.1, 0.95, 1.),
-                Vec3::new(0.95, 1.1, 1.),
-            ]),
-        },
-    );
-
-    commands.insert_resource(PlayerAnimations {
-        idle_animation: animations.add(idle_animation),
-        walk_animation: animations.add(walk_animation),
-    });
-
     let root = commands
         .spawn((
             SpatialBundle::default(),
             Name::new("Player"),
-            RigidBody::Dynamic,
-            Collider::ball((PLAYER_SIZE * 0.7) / 2.),
-            ColliderMassProperties::Density(0.0),
-            GravityScale(0.),
-            Velocity::zero(),
-            LockedAxes::ROTATION_LOCKED,
+            (
+                RigidBody::Dynamic,
+                Collider::ball((PLAYER_SIZE * 0.7) / 2.),
+                CollisionGroups::new(PLAYER_GROUP, Group::ALL),
+                ColliderMassProperties::Density(0.0),
+                GravityScale(0.),
+                Velocity::zero(),
+                LockedAxes::ROTATION_LOCKED,
+                ActiveEvents::COLLISION_EVENTS,
+            ),
+            MovementController::new(PLAYER_SPEED, PLAYER_ACCELERATION, PLAYER_FRICTION),
+            Health::new(PLAYER_HEALTH),
+            Stats::new([
+                (Stat::MoveSpeed, PLAYER_SPEED),
+                (Stat::MaxHealth, PLAYER_HEALTH),
+                (Stat::PickupRadius, PLAYER_MAGNET_RADIUS),
+            ]),
+            StatusEffects::default(),
+            Inventory::new(WEAPON_SLOTS, PASSIVE_SLOTS),
+            HitInvulnerability(PLAYER_INVULNERABILITY),
+            Dash::new(DASH_COOLDOWN),
+            Experience::default(),
+            Magnet {
+                radius: PLAYER_MAGNET_RADIUS,
+            },
             Player,
+            RunEntity,
         ))
         .id();
     let body_anchor = commands
-        .spawn((SpatialBundle::default(), anchor_name, animation_player))
+        .spawn((
+            SpatialBundle::default(),
+            anchor_name,
+            animation_player,
+            player_animation_machine(&animations),
+            PlayerAnchor,
+        ))
         .id();
     let eye_anchor = commands
         .spawn((SpatialBundle::default(), eye_anchor_name))
         .id();
+    let eye_sheet = sheets.get(&handles.player_eyes);
+    let eye_animator = eye_sheet
+        .map(|sheet| SpriteAnimator::from_sheet(handles.player_eyes.id(), sheet))
+        .unwrap_or_default();
+    // The eyes stay open, blinking over it each time `blink_eyes` sees the open clip finish
+    let eye_machine = AnimationStateMachine::new("open", AnimationMotion::Sprite("open".into()))
+        .with_overlay(
+            AnimationOverlay::new(AnimationMotion::Sprite("blink".into()), 0.0)
+                .when(AnimationCondition::Triggered("blink".into())),
+        );
     let body_sprite = commands
         .spawn((
             SpriteBundle {
@@ -186,89 +185,190 @@ pub fn spawn_player(
                     custom_size: Some(Vec2::splat(PLAYER_SIZE)),
                     ..default()
                 },
-                texture_atlas: handles.player_eyes.clone(),
+                texture_atlas: eye_sheet
+                    .map(|sheet| sheet.atlas.clone())
+                    .unwrap_or_default(),
                 transform: Transform::from_translation(Vec3::new(0., 0., 1.)),
                 ..default()
             },
             PlayerEyes,
             eye_sprite_name,
-            AnimationTimer(Timer::from_seconds(2.5, TimerMode::Once)),
-            Animations {
-                animations: vec![Animation {
-                    current_frame: 0,
-                    frames: vec![
-                        AnimationFrame {
-                            index: 0,
-                            time: 2.5,
-                        },
-                        AnimationFrame {
-                            index: 1,
-                            time: 0.15,
-                        },
-                    ],
-                }],
-                current_animation: 0,
-            },
+            eye_animator,
+            eye_machine,
         ))
         .id();
 
-    commands.entity(root).push_children(&[body_anchor]);
+    commands
+        .entity(root)
+        .insert(StatusTint::new(body_sprite, PLAYER_COLOUR))
+        .push_children(&[body_anchor]);
     commands
         .entity(body_anchor)
         .push_children(&[eye_anchor, body_sprite]);
     commands.entity(eye_anchor).push_children(&[eye_sprite]);
 }
 
-pub fn player_movement(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut player_query: Query<&mut Velocity, With<Player>>,
-    animations: Res<PlayerAnimations>,
-    mut animation_query: Query<&mut AnimationPlayer>,
+/// Idle, walk, dash and hurt states for the player's body, with a flinch over them when hit
+fn player_animation_machine(animations: &PlayerAnimations) -> AnimationStateMachine {
+    let looping = |clip: &Handle<AnimationClip>| AnimationMotion::Transform {
+        clip: clip.clone(),
+        repeat: true,
+    };
+    AnimationStateMachine::new("idle", looping(&animations.idle_animation))
+        .with_state("walk", looping(&animations.walk_animation))
+        .with_state(
+            "dash",
+            AnimationMotion::Transform {
+                clip: animations.dash_animation.clone(),
+                repeat: false,
+            },
+        )
+        .with_state("hurt", looping(&animations.hurt_animation))
+        .with_transition(
+            AnimationTransition::new(None, "dash", 0.05)
+                .when(AnimationCondition::True("is_dashing".into())),
+        )
+        .with_transition(
+            AnimationTransition::new(None, "hurt", 0.05)
+                .when(AnimationCondition::True("is_hurt".into())),
+        )
+        .with_transition(
+            AnimationTransition::new(Some("dash"), "idle", 0.25)
+                .when(AnimationCondition::False("is_dashing".into())),
+        )
+        .with_transition(
+            AnimationTransition::new(Some("hurt"), "idle", 0.25)
+                .when(AnimationCondition::False("is_hurt".into())),
+        )
+        .with_transition(
+            AnimationTransition::new(Some("idle"), "walk", 0.25)
+                .when(AnimationCondition::Greater("speed".into(), 0.0)),
+        )
+        .with_transition(
+            AnimationTransition::new(Some("walk"), "idle", 0.25)
+                .when(AnimationCondition::LessOrEqual("speed".into(), 0.0)),
+        )
+        .with_overlay(
+            AnimationOverlay::new(
+                AnimationMotion::Transform {
+                    clip: animations.hit_animation.clone(),
+                    repeat: false,
+                },
+                0.05,
+            )
+            .when(AnimationCondition::Triggered("hit".into())),
+        )
+}
+
+/// Starts a dash when asked for and off cooldown, and keeps the burst going until it runs out
+pub fn update_dash(
+    mut commands: Commands,
+    time: Res<Time>,
+    input: Res<PlayerInput>,
+    mut player_query: Query<
+        (
+            Entity,
+            &mut Dash,
+            &mut MovementController,
+            &mut CollisionGroups,
+            &StatusEffects,
+        ),
+        With<Player>,
+    >,
+    invulnerable_query: Query<&Invulnerable>,
 ) {
-    if let Ok(mut velocity) = player_query.get_single_mut() {
-        let mut direction = Vec2::ZERO;
+    let Ok((entity, mut dash, mut controller, mut groups, effects)) = player_query.get_single_mut()
+    else {
+        return;
+    };
+    dash.cooldown.tick(time.delta());
+    if input.movement != Vec2::ZERO {
+        dash.facing = input.movement.normalize();
+    }
 
-        if keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::A) {
-            direction += Vec2::new(-1.0, 0.0);
-        }
-        if keyboard_input.pressed(KeyCode::Right) || keyboard_input.pressed(KeyCode::D) {
-            direction += Vec2::new(1.0, 0.0);
-        }
-        if keyboard_input.pressed(KeyCode::Up) || keyboard_input.pressed(KeyCode::W) {
-            direction += Vec2::new(0.0, 1.0);
-        }
-        if keyboard_input.pressed(KeyCode::Down) || keyboard_input.pressed(KeyCode::S) {
-            direction += Vec2::new(0.0, -1.0);
+    if let Some(active) = &mut dash.active {
+        if !active.tick(time.delta()).finished() {
+            controller.drive(dash.direction * DASH_SPEED * effects.speed_multiplier());
+            return;
         }
+        dash.active = None;
+        *groups = CollisionGroups::new(PLAYER_GROUP, Group::ALL);
+    }
 
-        if direction.length_squared() > 0.0 {
-            direction = direction.normalize();
+    if input.dash && dash.cooldown.finished() && effects.can_act() {
+        dash.direction = dash.facing;
+        dash.active = Some(Timer::from_seconds(DASH_DURATION, TimerMode::Once));
+        dash.cooldown.reset();
+        controller.drive(dash.direction * DASH_SPEED * effects.speed_multiplier());
+        // Mobs stop colliding with the player, and nothing else can hurt it either
+        *groups = CollisionGroups::new(PLAYER_GROUP, Group::ALL.difference(MOB_GROUP));
+        // A longer invulnerability from a recent hit is kept rather than cut short
+        let hit_invulnerable = invulnerable_query
+            .get(entity)
+            .is_ok_and(|invulnerable| invulnerable.remaining_secs() >= DASH_DURATION);
+        if !hit_invulnerable {
+            commands
+                .entity(entity)
+                .insert(Invulnerable(Timer::from_seconds(
+                    DASH_DURATION,
+                    TimerMode::Once,
+                )));
         }
+    }
+}
 
-        velocity.linvel = direction * PLAYER_SPEED;
+pub fn player_movement(
+    input: Res<PlayerInput>,
+    mut player_query: Query<(&mut MovementController, &Dash), With<Player>>,
+) {
+    if let Ok((mut controller, dash)) = player_query.get_single_mut() {
+        // The dash drives the player until it ends
+        if dash.is_dashing() {
+            return;
+        }
+        controller.desired = input.movement.clamp_length_max(1.0);
+    }
+}
 
-        if let Ok(mut animation_player) = animation_query.get_single_mut() {
-            if direction.length_squared() > 0.0 {
-                animation_player
-                    .play_with_transition(
-                        animations.walk_animation.clone_weak(),
-                        Duration::from_millis(250),
-                    )
-                    .repeat();
-            } else {
-                animation_player
-                    .play_with_transition(
-                        animations.idle_animation.clone_weak(),
-                        Duration::from_millis(250),
-                    )
-                    .repeat();
-            }
+pub fn blink_eyes(
+    mut finished_events: EventReader<AnimationFinished>,
+    mut eyes_query: Query<&mut AnimationStateMachine, With<PlayerEyes>>,
+) {
+    for event in finished_events.read() {
+        if event.clip != "open" {
+            continue;
+        }
+        if let Ok(mut machine) = eyes_query.get_mut(event.entity) {
+            machine.trigger("blink");
         }
     }
 }
 
+/// Feeds the player's movement, dash and hits to its body's animation state machine
+pub fn update_player_animation(
+    player_query: Query<(&MovementController, &Dash, Has<Invulnerable>), With<Player>>,
+    hit_query: Query<(), (With<Player>, Added<Invulnerable>)>,
+    mut machine_query: Query<&mut AnimationStateMachine, With<PlayerAnchor>>,
+    mut hurt: Local<bool>,
+) {
+    let (Ok((controller, dash, invulnerable)), Ok(mut machine)) =
+        (player_query.get_single(), machine_query.get_single_mut())
+    else {
+        return;
+    };
+    // Dashes make the player invulnerable too, so only count invulnerability that starts outside one
+    if !invulnerable {
+        *hurt = false;
+    } else if !hit_query.is_empty() && !dash.is_dashing() {
+        *hurt = true;
+        machine.trigger("hit");
+    }
+    machine.set_float("speed", controller.desired.length());
+    machine.set_bool("is_dashing", dash.is_dashing());
+    machine.set_bool("is_hurt", *hurt);
+}
+
 pub fn lookat_nearest_target(
-    mut gizmos: Gizmos,
     target: Query<&Transform, (With<NearestMob>, Without<Player>, Without<PlayerEyes>)>,
     player: Query<&Transform, With<Player>>,
     mut player_eyes: Query<&mut Transform, (With<PlayerEyes>, Without<Player>)>,
@@ -287,12 +387,6 @@ pub fn lookat_nearest_target(
 
                 eyes.translation.x = direction.x * 8.;
                 eyes.translation.y = direction.y * 8.;
-
-                gizmos.line_2d(
-                    player_transform.translation.xy(),
-                    target_transform.translation.xy(),
-                    Color::RED,
-                );
             } else {
                 eyes.translation.x = 0.;
                 eyes.translation.y = 0.;
@@ -300,3 +394,16 @@ pub fn lookat_nearest_target(
         }
     }
 }
+
+pub fn player_death(
+    mut death_events: EventReader<DeathEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    player_query: Query<(), With<Player>>,
+) {
+    for event in death_events.read() {
+        if player_query.contains(event.entity) {
+            info!("Player died, ending the run");
+            next_state.set(AppState::GameOver);
+        }
+    }
+}
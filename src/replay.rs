@@ -0,0 +1,563 @@
+use crate::prelude::*;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"RSRP";
+/// Bumped whenever the layout below changes, older files are rejected rather than misread
+const VERSION: u16 = 4;
+const REPLAY_DIR: &str = "replays";
+/// Extra frames `verify` allows past the end of the recording before giving up on a run that
+/// never finishes playing back
+const VERIFY_MARGIN: usize = 600;
+
+// Flags at the start of every tick, saying which optional fields follow
+const MOVEMENT_CHANGED: u8 = 1 << 0;
+const UPGRADE_CHOSEN: u8 = 1 << 1;
+const PAUSED: u8 = 1 << 2;
+const DASHED: u8 = 1 << 3;
+const CHEST_CLOSED: u8 = 1 << 4;
+
+/// Everything the player did on one tick of a run, each lasting `TIMESTEP`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayTick {
+    pub movement: Vec2,
+    pub upgrade: Option<u8>,
+    pub paused: bool,
+    pub dash: bool,
+    pub chest_closed: bool,
+}
+
+/// A recorded run: its seed, the input for every tick and a checksum of how it ended
+///
+/// Stored as the magic bytes, a little-endian `u16` version and `u64` seed, a varint tick
+/// count, the ticks and finally the `u64` checksum. Each tick is a flags byte, then the
+/// movement as two `f32`s when it changed since the last tick and the upgrade index when one
+/// was chosen.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub ticks: Vec<ReplayTick>,
+    pub checksum: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("could not access replay: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a replay file")]
+    NotAReplay,
+    #[error("replay version {0} is not supported, expected {VERSION}")]
+    UnsupportedVersion(u16),
+    #[error("replay is truncated or corrupt")]
+    Corrupt,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        write_varint(&mut bytes, self.ticks.len() as u64);
+
+        let mut movement = Vec2::ZERO;
+        for tick in &self.ticks {
+            let mut flags = 0;
+            if tick.movement != movement {
+                flags |= MOVEMENT_CHANGED;
+            }
+            if tick.upgrade.is_some() {
+                flags |= UPGRADE_CHOSEN;
+            }
+            if tick.paused {
+                flags |= PAUSED;
+            }
+            if tick.dash {
+                flags |= DASHED;
+            }
+            if tick.chest_closed {
+                flags |= CHEST_CLOSED;
+            }
+            bytes.push(flags);
+            if flags & MOVEMENT_CHANGED != 0 {
+                bytes.extend_from_slice(&tick.movement.x.to_le_bytes());
+                bytes.extend_from_slice(&tick.movement.y.to_le_bytes());
+                movement = tick.movement;
+            }
+            if let Some(index) = tick.upgrade {
+                bytes.push(index);
+            }
+        }
+
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = ByteReader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = u64::from_le_bytes(reader.array()?);
+
+        let count = reader.varint()? as usize;
+        // Every tick takes at least a byte, so a bogus count can't reserve too much
+        let mut ticks = Vec::with_capacity(count.min(bytes.len()));
+        let mut movement = Vec2::ZERO;
+        for _ in 0..count {
+            let flags = reader.byte()?;
+            if flags & MOVEMENT_CHANGED != 0 {
+                let x = f32::from_le_bytes(reader.array()?);
+                let y = f32::from_le_bytes(reader.array()?);
+                movement = Vec2::new(x, y);
+            }
+            let upgrade = if flags & UPGRADE_CHOSEN != 0 {
+                Some(reader.byte()?)
+            } else {
+                None
+            };
+            ticks.push(ReplayTick {
+                movement,
+                upgrade,
+                paused: flags & PAUSED != 0,
+                dash: flags & DASHED != 0,
+                chest_closed: flags & CHEST_CLOSED != 0,
+            });
+        }
+
+        let checksum = u64::from_le_bytes(reader.array()?);
+        if !reader.bytes.is_empty() {
+            return Err(ReplayError::Corrupt);
+        }
+        Ok(Self {
+            seed,
+            ticks,
+            checksum,
+        })
+    }
+}
+
+/// LEB128, so small numbers like tick counts take a few bytes instead of eight
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < count {
+            return Err(ReplayError::Corrupt);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn byte(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReplayError::Corrupt)
+    }
+}
+
+/// Hash of the state a run ended in, for spotting replays that play out differently
+pub fn state_checksum(
+    director: &WaveDirector,
+    player_query: &Query<(&Transform, &Health, &Experience), With<Player>>,
+    mob_query: &Query<&Transform, With<Mob>>,
+) -> u64 {
+    let mut checksum = Fnv::default();
+    checksum.write(director.elapsed.to_bits());
+    if let Ok((transform, health, experience)) = player_query.get_single() {
+        checksum.write(transform.translation.x.to_bits());
+        checksum.write(transform.translation.y.to_bits());
+        checksum.write(health.current.to_bits());
+        checksum.write(experience.level);
+        checksum.write(experience.current);
+    }
+    // Summed so the result doesn't depend on query order
+    let mut mobs = 0u64;
+    for transform in mob_query {
+        let mut mob = Fnv::default();
+        mob.write(transform.translation.x.to_bits());
+        mob.write(transform.translation.y.to_bits());
+        mobs = mobs.wrapping_add(mob.0);
+    }
+    checksum.write(mob_query.iter().len() as u32);
+    checksum.write(mobs as u32);
+    checksum.write((mobs >> 32) as u32);
+    checksum.0
+}
+
+/// FNV-1a, which unlike the std hashers is the same on every platform and build
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, value: u32) {
+        for byte in value.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Inputs of the run in progress, saved under `replays/` at game over
+#[derive(Resource, Default)]
+pub struct ReplayRecorder {
+    recording: bool,
+    ticks: Vec<ReplayTick>,
+}
+
+/// Records every run, so a bug can be reported along with the run that hit it
+pub struct ReplayRecorderPlugin;
+
+impl Plugin for ReplayRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .add_systems(OnExit(AppState::MainMenu), start_recording)
+            .add_systems(FixedUpdate, record_tick.in_set(TickSet::Record))
+            .add_systems(OnEnter(AppState::GameOver), save_recording);
+    }
+}
+
+pub fn start_recording(mut recorder: ResMut<ReplayRecorder>) {
+    recorder.recording = true;
+    recorder.ticks.clear();
+}
+
+pub fn record_tick(
+    input: Res<PlayerInput>,
+    state: Res<State<AppState>>,
+    mut chosen_events: EventReader<UpgradeChosen>,
+    mut closed_events: EventReader<ChestClosed>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let upgrade = chosen_events.read().last().map(|chosen| chosen.0 as u8);
+    let chest_closed = closed_events.read().count() > 0;
+    if !recorder.recording {
+        return;
+    }
+    recorder.ticks.push(ReplayTick {
+        movement: input.movement,
+        upgrade,
+        paused: *state.get() == AppState::Paused,
+        dash: input.dash,
+        chest_closed,
+    });
+}
+
+pub fn save_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    rng: Res<GameRng>,
+    director: Res<WaveDirector>,
+    player_query: Query<(&Transform, &Health, &Experience), With<Player>>,
+    mob_query: Query<&Transform, With<Mob>>,
+) {
+    recorder.recording = false;
+    let replay = Replay {
+        seed: rng.seed(),
+        ticks: std::mem::take(&mut recorder.ticks),
+        checksum: state_checksum(&director, &player_query, &mob_query),
+    };
+    // Stamped with when the run ended, so replaying a seed doesn't overwrite earlier recordings
+    let ended = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let path = PathBuf::from(REPLAY_DIR).join(format!("{}-{ended}.replay", replay.seed));
+    match replay.save(&path) {
+        Ok(()) => info!("Saved replay to {}", path.display()),
+        Err(error) => error!("Could not save replay to {}: {error}", path.display()),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackOutcome {
+    Matched,
+    /// Ended differently from the recording, or ended after a different number of ticks
+    Desynced,
+}
+
+/// Replay being played back in place of the player's input
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    started: bool,
+    cursor: usize,
+    pub outcome: Option<PlaybackOutcome>,
+}
+
+impl ReplayPlayback {
+    fn tick(&self) -> Option<&ReplayTick> {
+        self.replay.ticks.get(self.cursor)
+    }
+}
+
+/// Plays a recorded run back once, then checks it ended in the same state
+pub struct ReplayPlaybackPlugin(pub Replay);
+
+impl Plugin for ReplayPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RunSeed(Some(self.0.seed)))
+            .insert_resource(ReplayPlayback {
+                replay: self.0.clone(),
+                started: false,
+                cursor: 0,
+                outcome: None,
+            })
+            .add_systems(Update, start_playback.run_if(in_state(AppState::MainMenu)))
+            .add_systems(
+                FixedUpdate,
+                (
+                    (play_pause, play_input)
+                        .in_set(TickSet::Input)
+                        .run_if(playback_running),
+                    advance_playback
+                        .in_set(TickSet::Record)
+                        .run_if(playback_running),
+                ),
+            )
+            .add_systems(OnEnter(AppState::GameOver), end_playback_early);
+    }
+}
+
+pub fn playback_running(playback: Res<ReplayPlayback>, state: Res<State<AppState>>) -> bool {
+    playback.started
+        && playback.outcome.is_none()
+        && matches!(
+            state.get(),
+            AppState::Playing | AppState::Paused | AppState::LevelUp | AppState::ChestOpening
+        )
+}
+
+pub fn start_playback(
+    mut playback: ResMut<ReplayPlayback>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if playback.started {
+        return;
+    }
+    playback.started = true;
+    next_state.set(AppState::Playing);
+}
+
+/// Pauses and resumes where the recording did, before the state transition for this tick
+pub fn play_pause(
+    playback: Res<ReplayPlayback>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(tick) = playback.tick() else {
+        return;
+    };
+    match (state.get(), tick.paused) {
+        (AppState::Playing, true) => next_state.set(AppState::Paused),
+        (AppState::Paused, false) => next_state.set(AppState::Playing),
+        _ => {}
+    }
+}
+
+pub fn play_input(
+    playback: Res<ReplayPlayback>,
+    mut input: ResMut<PlayerInput>,
+    mut chosen_events: EventWriter<UpgradeChosen>,
+    mut closed_events: EventWriter<ChestClosed>,
+) {
+    let Some(tick) = playback.tick() else {
+        return;
+    };
+    input.movement = tick.movement;
+    input.dash = tick.dash;
+    if let Some(index) = tick.upgrade {
+        chosen_events.send(UpgradeChosen(index as usize));
+    }
+    if tick.chest_closed {
+        closed_events.send(ChestClosed);
+    }
+}
+
+/// Moves on to the next tick, and once every tick has played checks the run ended up where the
+/// recording did
+pub fn advance_playback(
+    mut playback: ResMut<ReplayPlayback>,
+    director: Res<WaveDirector>,
+    player_query: Query<(&Transform, &Health, &Experience), With<Player>>,
+    mob_query: Query<&Transform, With<Mob>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    playback.cursor += 1;
+    if playback.tick().is_some() {
+        return;
+    }
+
+    let checksum = state_checksum(&director, &player_query, &mob_query);
+    let outcome = if checksum == playback.replay.checksum {
+        info!("Replay matched after {} ticks", playback.cursor);
+        PlaybackOutcome::Matched
+    } else {
+        error!(
+            "Replay desynced: checksum {checksum:016x}, expected {:016x}",
+            playback.replay.checksum
+        );
+        PlaybackOutcome::Desynced
+    };
+    playback.outcome = Some(outcome);
+    next_state.set(AppState::GameOver);
+}
+
+/// Catches runs that end before the recording does
+pub fn end_playback_early(mut playback: ResMut<ReplayPlayback>) {
+    if !playback.started || playback.outcome.is_some() {
+        return;
+    }
+    error!(
+        "Replay desynced: run ended after {} of {} ticks",
+        playback.cursor,
+        playback.replay.ticks.len()
+    );
+    playback.outcome = Some(PlaybackOutcome::Desynced);
+}
+
+/// Plays a replay back without a window as fast as possible, returning whether it matched
+pub fn verify(replay: Replay) -> PlaybackOutcome {
+    let mut app = headless_app();
+    app.add_plugins(ReplayPlaybackPlugin(replay));
+    app.finish();
+    app.cleanup();
+    // Only frames after the run starts count, loading takes however long it takes
+    let mut frames = 0;
+    loop {
+        app.update();
+        let playback = app.world.resource::<ReplayPlayback>();
+        if let Some(outcome) = playback.outcome {
+            return outcome;
+        }
+        if playback.started {
+            frames += 1;
+        }
+        if frames > playback.replay.ticks.len() + VERIFY_MARGIN {
+            error!(
+                "Replay desynced: still playing {frames} frames into {} ticks",
+                playback.replay.ticks.len()
+            );
+            return PlaybackOutcome::Desynced;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        Replay {
+            seed: 0x0123_4567_89ab_cdef,
+            ticks: vec![
+                ReplayTick::default(),
+                ReplayTick {
+                    movement: Vec2::new(0.6, -0.8),
+                    dash: true,
+                    ..default()
+                },
+                ReplayTick {
+                    movement: Vec2::new(0.6, -0.8),
+                    upgrade: Some(2),
+                    ..default()
+                },
+                ReplayTick {
+                    paused: true,
+                    chest_closed: true,
+                    ..default()
+                },
+            ],
+            checksum: 0xfeed_face_dead_beef,
+        }
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let replay = replay();
+        let bytes = replay.to_bytes();
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(bytes[4..6], VERSION.to_le_bytes());
+        assert_eq!(bytes[bytes.len() - 8..], replay.checksum.to_le_bytes());
+        assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let bytes = replay().to_bytes();
+        assert!(matches!(
+            Replay::from_bytes(b"nope"),
+            Err(ReplayError::NotAReplay)
+        ));
+        let mut old = bytes.clone();
+        old[4..6].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        assert!(matches!(
+            Replay::from_bytes(&old),
+            Err(ReplayError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            Replay::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReplayError::Corrupt)
+        ));
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(
+            Replay::from_bytes(&trailing),
+            Err(ReplayError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn checksum_hash_is_stable() {
+        let mut checksum = Fnv::default();
+        checksum.write(42);
+        checksum.write(u32::MAX);
+        assert_eq!(checksum.0, 0x947c_5f73_8716_7c4b);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::prelude::*;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+/// Source of gameplay randomness, so a run plays out the same way from the same seed
+///
+/// Each subsystem draws from its own named stream, derived from the run seed and the name, so
+/// adding a random call in one system doesn't change what the others roll.
+#[derive(Resource)]
+pub struct GameRng {
+    seed: u64,
+    streams: HashMap<&'static str, StdRng>,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Random stream for the subsystem called `name`, forked from the run seed on first use
+    pub fn stream(&mut self, name: &'static str) -> &mut StdRng {
+        let seed = self.seed;
+        self.streams
+            .entry(name)
+            .or_insert_with(|| StdRng::seed_from_u64(stream_seed(seed, name)))
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::from_seed(0)
+    }
+}
+
+/// Mixes the run seed with a stream name, stable across platforms and builds
+fn stream_seed(seed: u64, name: &str) -> u64 {
+    // FNV-1a over the name, then a splitmix64 finaliser over the combination
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in name.bytes() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    let mut z = (seed ^ hash).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Seed the next run starts from, picked at random when `None`
+#[derive(Resource, Default)]
+pub struct RunSeed(pub Option<u64>);
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRng>()
+            .init_resource::<RunSeed>()
+            .add_systems(OnExit(AppState::MainMenu), seed_run);
+    }
+}
+
+pub fn seed_run(run_seed: Res<RunSeed>, mut rng: ResMut<GameRng>) {
+    let seed = run_seed.0.unwrap_or_else(rand::random);
+    info!("Starting run with seed {seed}");
+    *rng = GameRng::from_seed(seed);
+}
@@ -0,0 +1,175 @@
+use crate::prelude::*;
+use bevy::utils::HashMap;
+
+use std::marker::PhantomData;
+
+/// Side length of a grid cell, roughly the size of a mob
+pub const DEFAULT_CELL_SIZE: f32 = 128.0;
+
+/// Buckets the positions of every entity with `T` by cell, rebuilt at the start of each frame
+///
+/// Positions are those from the previous physics step, and despawned entities may linger until
+/// the next rebuild, so callers should treat results as candidates rather than live entities.
+#[derive(Resource)]
+pub struct SpatialGrid<T> {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<(Entity, Vec2)>>,
+    /// Smallest and largest occupied cell, bounding how far searches have to look
+    bounds: Option<(IVec2, IVec2)>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for SpatialGrid<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl<T> SpatialGrid<T> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            bounds: None,
+            _marker: PhantomData,
+        }
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn clear(&mut self) {
+        // Reuse buckets that were filled last frame, dropping ones the mobs have moved away from
+        self.cells.retain(|_, entries| {
+            let occupied = !entries.is_empty();
+            entries.clear();
+            occupied
+        });
+        self.bounds = None;
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec2) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push((entity, position));
+        self.bounds = Some(match self.bounds {
+            Some((min, max)) => (min.min(cell), max.max(cell)),
+            None => (cell, cell),
+        });
+    }
+
+    /// Entities within `radius` of `position`, in no particular order
+    pub fn within_radius(
+        &self,
+        position: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = (Entity, Vec2)> + '_ {
+        let min = self.cell(position - Vec2::splat(radius));
+        let max = self.cell(position + Vec2::splat(radius));
+        let radius_sq = radius * radius;
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(move |(_, other)| other.distance_squared(position) <= radius_sq)
+    }
+
+    /// Up to `k` entities within `max_distance` of `position`, closest first
+    pub fn k_nearest(&self, position: Vec2, k: usize, max_distance: f32) -> Vec<(Entity, Vec2)> {
+        let mut found: Vec<(f32, Entity, Vec2)> = Vec::new();
+        let Some((min, max)) = self.bounds else {
+            return Vec::new();
+        };
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let centre = self.cell(position);
+        let max_distance_sq = max_distance * max_distance;
+        // Rings needed before the search square covers every occupied cell
+        let last_ring = (centre - min).abs().max((max - centre).abs()).max_element();
+
+        for ring in 0..=last_ring {
+            for cell in ring_cells(centre, ring) {
+                let Some(entries) = self.cells.get(&cell) else {
+                    continue;
+                };
+                for &(entity, other) in entries {
+                    let distance_sq = other.distance_squared(position);
+                    if distance_sq <= max_distance_sq {
+                        found.push((distance_sq, entity, other));
+                    }
+                }
+            }
+
+            // Anything outside the rings searched so far is at least this far away
+            let searched = ring as f32 * self.cell_size;
+            if searched >= max_distance {
+                break;
+            }
+            if found.len() >= k {
+                found.select_nth_unstable_by(k - 1, |a, b| a.0.total_cmp(&b.0));
+                found.truncate(k);
+                if found[k - 1].0 <= searched * searched {
+                    break;
+                }
+            }
+        }
+
+        found.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        found.truncate(k);
+        found
+            .into_iter()
+            .map(|(_, entity, position)| (entity, position))
+            .collect()
+    }
+
+    /// Closest entity within `max_distance` of `position`
+    pub fn nearest(&self, position: Vec2, max_distance: f32) -> Option<(Entity, Vec2)> {
+        self.k_nearest(position, 1, max_distance).into_iter().next()
+    }
+}
+
+/// Cells on the border of the square `ring` cells out from `centre`
+fn ring_cells(centre: IVec2, ring: i32) -> impl Iterator<Item = IVec2> {
+    (-ring..=ring)
+        .flat_map(move |y| (-ring..=ring).map(move |x| IVec2::new(x, y)))
+        .filter(move |offset| offset.abs().max_element() == ring)
+        .map(move |offset| centre + offset)
+}
+
+/// Keeps a `SpatialGrid<T>` of every entity with the component `T`
+pub struct SpatialGridPlugin<T> {
+    cell_size: f32,
+    _marker: PhantomData<T>,
+}
+
+impl<T> SpatialGridPlugin<T> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Component> Plugin for SpatialGridPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpatialGrid::<T>::new(self.cell_size))
+            .add_systems(
+                FixedUpdate,
+                rebuild_spatial_grid::<T>.in_set(TickSet::Prepare),
+            );
+    }
+}
+
+pub fn rebuild_spatial_grid<T: Component>(
+    mut grid: ResMut<SpatialGrid<T>>,
+    query: Query<(Entity, &Transform), With<T>>,
+) {
+    grid.clear();
+    for (entity, transform) in query.iter() {
+        grid.insert(entity, transform.translation.xy());
+    }
+}
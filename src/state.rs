@@ -0,0 +1,59 @@
+use crate::prelude::*;
+
+const STATES: [AppState; 7] = [
+    AppState::Loading,
+    AppState::MainMenu,
+    AppState::Playing,
+    AppState::Paused,
+    AppState::LevelUp,
+    AppState::ChestOpening,
+    AppState::GameOver,
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, States)]
+pub enum AppState {
+    #[default]
+    Loading,
+    MainMenu,
+    Playing,
+    Paused,
+    LevelUp,
+    ChestOpening,
+    GameOver,
+}
+
+/// Despawns the entity when the app leaves the given state
+#[derive(Component)]
+pub struct DespawnOnExit(pub AppState);
+
+/// Part of the current run, despawned once the game over screen is left
+#[derive(Component)]
+pub struct RunEntity;
+
+pub struct StatePlugin;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<AppState>()
+            .add_systems(OnExit(AppState::GameOver), despawn_run_entities);
+        for state in STATES {
+            app.add_systems(OnExit(state), despawn_on_exit(state));
+        }
+    }
+}
+
+fn despawn_on_exit(state: AppState) -> impl FnMut(Commands, Query<(Entity, &DespawnOnExit)>) {
+    move |mut commands, query| {
+        for (entity, despawn) in query.iter() {
+            if despawn.0 == state {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+pub fn despawn_run_entities(mut commands: Commands, query: Query<Entity, With<RunEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,174 @@
+use crate::prelude::*;
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Stat {
+    MoveSpeed,
+    MaxHealth,
+    /// Radius experience gems are pulled in from
+    PickupRadius,
+    /// Fraction taken off every weapon's cooldown
+    CooldownReduction,
+    /// Scale on weapon reach and projectile size
+    Area,
+    /// Damage taken off every hit
+    Armor,
+    /// Chance of being offered an extra upgrade
+    Luck,
+}
+
+const STAT_COUNT: usize = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum Modifier {
+    /// Added to the base value
+    Add(f32),
+    /// Scales the base value plus every addition
+    Multiply(f32),
+}
+
+/// Where a modifier came from, so it can be taken off again
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ModifierSource {
+    /// Chosen on level up, kept for the rest of the run
+    Upgrade,
+    /// Current status effects, replaced as they change
+    Status,
+    /// A passive item, by id, replaced as it levels up
+    Item(String),
+}
+
+struct AppliedModifier {
+    source: ModifierSource,
+    stat: Stat,
+    modifier: Modifier,
+}
+
+/// Base values of an entity's stats and the modifiers on them. Effective values are only worked
+/// out again once something changes, so reading them is cheap.
+#[derive(Component)]
+pub struct Stats {
+    base: [f32; STAT_COUNT],
+    modifiers: Vec<AppliedModifier>,
+    effective: [f32; STAT_COUNT],
+    dirty: bool,
+}
+
+impl Stats {
+    /// Stats with the given base values, zero for the rest. `Area` starts at one.
+    pub fn new(base: impl IntoIterator<Item = (Stat, f32)>) -> Self {
+        let mut values = [0.0; STAT_COUNT];
+        values[Stat::Area as usize] = 1.0;
+        for (stat, value) in base {
+            values[stat as usize] = value;
+        }
+        Self {
+            base: values,
+            modifiers: Vec::new(),
+            effective: values,
+            dirty: false,
+        }
+    }
+
+    /// Effective value as of the last refresh, at the start of the frame
+    pub fn get(&self, stat: Stat) -> f32 {
+        self.effective[stat as usize]
+    }
+
+    pub fn add_modifier(&mut self, source: ModifierSource, stat: Stat, modifier: Modifier) {
+        self.modifiers.push(AppliedModifier {
+            source,
+            stat,
+            modifier,
+        });
+        self.dirty = true;
+    }
+
+    /// The only modifier `source` puts on `stat`, if it has exactly one
+    pub fn modifier(&self, source: &ModifierSource, stat: Stat) -> Option<Modifier> {
+        let mut matching = self
+            .modifiers
+            .iter()
+            .filter(|applied| applied.source == *source && applied.stat == stat);
+        match (matching.next(), matching.next()) {
+            (Some(applied), None) => Some(applied.modifier),
+            _ => None,
+        }
+    }
+
+    /// Swaps whatever `source` puts on `stat` for `modifier`, or takes it off for `None`
+    pub fn set_modifier(&mut self, source: ModifierSource, stat: Stat, modifier: Option<Modifier>) {
+        self.modifiers
+            .retain(|applied| applied.source != source || applied.stat != stat);
+        if let Some(modifier) = modifier {
+            self.modifiers.push(AppliedModifier {
+                source,
+                stat,
+                modifier,
+            });
+        }
+        self.dirty = true;
+    }
+
+    fn refresh(&mut self) {
+        let mut added = self.base;
+        let mut multiplier = [1.0; STAT_COUNT];
+        for applied in self.modifiers.iter() {
+            let index = applied.stat as usize;
+            match applied.modifier {
+                Modifier::Add(amount) => added[index] += amount,
+                Modifier::Multiply(scale) => multiplier[index] *= scale,
+            }
+        }
+        for index in 0..STAT_COUNT {
+            self.effective[index] = added[index] * multiplier[index];
+        }
+        self.dirty = false;
+    }
+}
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (refresh_stats, apply_stats)
+                .chain()
+                .in_set(TickSet::Prepare),
+        );
+    }
+}
+
+pub fn refresh_stats(mut query: Query<&mut Stats, Changed<Stats>>) {
+    for mut stats in &mut query {
+        if stats.dirty {
+            stats.refresh();
+        }
+    }
+}
+
+/// Hands effective stats to the components that act on them
+pub fn apply_stats(
+    query: Query<(Entity, &Stats), Changed<Stats>>,
+    mut controller_query: Query<&mut MovementController>,
+    mut magnet_query: Query<&mut Magnet>,
+    mut health_query: Query<&mut Health>,
+) {
+    for (entity, stats) in query.iter() {
+        if let Ok(mut controller) = controller_query.get_mut(entity) {
+            controller.max_speed = stats.get(Stat::MoveSpeed);
+        }
+        if let Ok(mut magnet) = magnet_query.get_mut(entity) {
+            magnet.radius = stats.get(Stat::PickupRadius);
+        }
+        if let Ok(mut health) = health_query.get_mut(entity) {
+            // Raising max health heals by the same amount, lowering it never kills
+            let max = stats.get(Stat::MaxHealth);
+            let gained = max - health.max;
+            health.max = max;
+            health.current = (health.current + gained.max(0.0)).min(max).max(0.0);
+        }
+    }
+}
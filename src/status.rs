@@ -0,0 +1,304 @@
+use crate::prelude::*;
+
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Seconds between damage ticks of burns and poisons
+const STATUS_TICK: f32 = 0.5;
+/// How far an affected sprite's colour moves towards the effect's tint
+const TINT_STRENGTH: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum StatusKind {
+    /// Cuts movement speed by `potency` per stack
+    Slow,
+    /// Deals `potency` damage per second
+    Burn,
+    /// Holds the target in place and stops it attacking or dashing
+    Freeze,
+    /// Deals `potency` damage per second per stack
+    Poison,
+    /// Holds the target in place and stops it attacking or dashing
+    Stun,
+}
+
+impl StatusKind {
+    /// Number of times the effect can stack, reapplying it past this only refreshes its duration
+    pub fn max_stacks(self) -> u32 {
+        match self {
+            StatusKind::Slow => 3,
+            StatusKind::Poison => 5,
+            StatusKind::Burn | StatusKind::Freeze | StatusKind::Stun => 1,
+        }
+    }
+
+    pub fn tint(self) -> Color {
+        match self {
+            StatusKind::Slow => Color::rgb(0.5, 0.6, 1.0),
+            StatusKind::Burn => Color::rgb(1.0, 0.4, 0.1),
+            StatusKind::Freeze => Color::rgb(0.6, 0.95, 1.0),
+            StatusKind::Poison => Color::rgb(0.4, 0.9, 0.2),
+            StatusKind::Stun => Color::rgb(1.0, 0.95, 0.3),
+        }
+    }
+}
+
+/// An effect as weapons and mobs inflict it, loaded from their data files
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// Seconds the effect lasts
+    pub duration: f32,
+    /// Slow fraction or damage per second, depending on the kind
+    #[serde(default)]
+    pub potency: f32,
+}
+
+/// An effect currently on an entity
+pub struct ActiveEffect {
+    pub kind: StatusKind,
+    pub potency: f32,
+    pub stacks: u32,
+    pub remaining: Timer,
+    tick: Timer,
+}
+
+impl ActiveEffect {
+    fn damage_per_second(&self) -> f32 {
+        match self.kind {
+            StatusKind::Burn | StatusKind::Poison => self.potency * self.stacks as f32,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Timed effects on an entity, in the order they were first applied
+#[derive(Component, Default)]
+pub struct StatusEffects {
+    pub effects: Vec<ActiveEffect>,
+}
+
+impl StatusEffects {
+    /// Adds `effect`, stacking onto and refreshing one of the same kind if there is one
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let duration = Duration::from_secs_f32(effect.duration);
+        match self
+            .effects
+            .iter_mut()
+            .find(|active| active.kind == effect.kind)
+        {
+            Some(active) => {
+                active.stacks = (active.stacks + 1).min(effect.kind.max_stacks());
+                active.potency = active.potency.max(effect.potency);
+                if active.remaining.remaining() < duration {
+                    active.remaining.set_duration(duration);
+                    active.remaining.reset();
+                }
+            }
+            None => {
+                self.effects.push(ActiveEffect {
+                    kind: effect.kind,
+                    potency: effect.potency,
+                    stacks: 1,
+                    remaining: Timer::new(duration, TimerMode::Once),
+                    tick: Timer::from_seconds(STATUS_TICK, TimerMode::Repeating),
+                });
+            }
+        }
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Scale on movement speed from slows, freezes and stuns
+    pub fn speed_multiplier(&self) -> f32 {
+        self.effects
+            .iter()
+            .map(|effect| match effect.kind {
+                StatusKind::Slow => (1.0 - effect.potency)
+                    .clamp(0.0, 1.0)
+                    .powi(effect.stacks as i32),
+                StatusKind::Freeze | StatusKind::Stun => 0.0,
+                StatusKind::Burn | StatusKind::Poison => 1.0,
+            })
+            .product()
+    }
+
+    /// Whether the entity can attack, charge or dash
+    pub fn can_act(&self) -> bool {
+        !self.has(StatusKind::Stun) && !self.has(StatusKind::Freeze)
+    }
+}
+
+/// Recolours `sprite` while its owner has any effects, towards the latest effect's tint
+#[derive(Component)]
+pub struct StatusTint {
+    pub sprite: Entity,
+    pub base: Color,
+    showing: Option<StatusKind>,
+}
+
+impl StatusTint {
+    pub fn new(sprite: Entity, base: Color) -> Self {
+        Self {
+            sprite,
+            base,
+            showing: None,
+        }
+    }
+}
+
+/// Asks for `effect` to be put on `target`
+#[derive(Event)]
+pub struct ApplyStatus {
+    pub target: Entity,
+    pub effect: StatusEffect,
+}
+
+#[derive(Event)]
+pub struct StatusApplied {
+    pub target: Entity,
+    pub kind: StatusKind,
+}
+
+#[derive(Event)]
+pub struct StatusExpired {
+    pub target: Entity,
+    pub kind: StatusKind,
+}
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ApplyStatus>()
+            .add_event::<StatusApplied>()
+            .add_event::<StatusExpired>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    apply_status_effects,
+                    tick_status_effects
+                        .before(apply_damage)
+                        .before(apply_movement),
+                )
+                    .chain()
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(
+                Update,
+                tint_status_effects.run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+pub fn apply_status_effects(
+    mut apply_events: EventReader<ApplyStatus>,
+    mut applied_events: EventWriter<StatusApplied>,
+    mut query: Query<&mut StatusEffects>,
+) {
+    for event in apply_events.read() {
+        if let Ok(mut effects) = query.get_mut(event.target) {
+            effects.apply(event.effect);
+            applied_events.send(StatusApplied {
+                target: event.target,
+                kind: event.effect.kind,
+            });
+        }
+    }
+}
+
+/// Counts effects down, deals their tick damage and slows their targets, through their stats when
+/// they have them. Ticks land through invulnerability, and never grant any.
+pub fn tick_status_effects(
+    time: Res<Time>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut expired_events: EventWriter<StatusExpired>,
+    mut query: Query<(Entity, &mut StatusEffects, Option<&mut MovementController>)>,
+    mut stats_query: Query<&mut Stats>,
+) {
+    for (entity, mut effects, controller) in &mut query {
+        for effect in effects.effects.iter_mut() {
+            effect.remaining.tick(time.delta());
+            let ticks = effect.tick.tick(time.delta()).times_finished_this_tick();
+            let damage = effect.damage_per_second() * STATUS_TICK * ticks as f32;
+            if damage > 0.0 {
+                damage_events.send(DamageEvent {
+                    target: entity,
+                    amount: damage,
+                    knockback: Vec2::ZERO,
+                    over_time: true,
+                });
+            }
+        }
+        effects.effects.retain(|effect| {
+            let expired = effect.remaining.finished();
+            if expired {
+                expired_events.send(StatusExpired {
+                    target: entity,
+                    kind: effect.kind,
+                });
+            }
+            !expired
+        });
+
+        let multiplier = effects.speed_multiplier();
+        if let Ok(mut stats) = stats_query.get_mut(entity) {
+            let modifier = (multiplier != 1.0).then_some(Modifier::Multiply(multiplier));
+            // Only touch the stats when the slow changes, so they are not worked out every frame
+            if stats.modifier(&ModifierSource::Status, Stat::MoveSpeed) != modifier {
+                stats.set_modifier(ModifierSource::Status, Stat::MoveSpeed, modifier);
+            }
+        } else if let Some(mut controller) = controller {
+            controller.speed_multiplier = multiplier;
+        }
+    }
+}
+
+pub fn tint_status_effects(
+    mut applied_events: EventReader<StatusApplied>,
+    mut expired_events: EventReader<StatusExpired>,
+    mut tint_query: Query<(&mut StatusTint, &StatusEffects)>,
+    mut sprite_query: Query<&mut Sprite>,
+) {
+    let applied = applied_events
+        .read()
+        .map(|event| (event.target, Some(event.kind)));
+    // Fall back to the newest effect left once the one shown runs out
+    let expired = expired_events.read().filter_map(|event| {
+        let (tint, effects) = tint_query.get(event.target).ok()?;
+        (tint.showing == Some(event.kind)).then(|| {
+            (
+                event.target,
+                effects.effects.last().map(|effect| effect.kind),
+            )
+        })
+    });
+    let changes: Vec<_> = applied.chain(expired).collect();
+
+    for (target, kind) in changes {
+        let Ok((mut tint, _)) = tint_query.get_mut(target) else {
+            continue;
+        };
+        tint.showing = kind;
+        if let Ok(mut sprite) = sprite_query.get_mut(tint.sprite) {
+            sprite.color = match kind {
+                Some(kind) => mix(tint.base, kind.tint(), TINT_STRENGTH),
+                None => tint.base,
+            };
+        }
+    }
+}
+
+fn mix(from: Color, to: Color, amount: f32) -> Color {
+    let [r, g, b, a] = from.as_rgba_f32();
+    let [to_r, to_g, to_b, _] = to.as_rgba_f32();
+    Color::rgba(
+        r + (to_r - r) * amount,
+        g + (to_g - g) * amount,
+        b + (to_b - b) * amount,
+        a,
+    )
+}
@@ -0,0 +1,125 @@
+use crate::prelude::*;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Transform animation described by curves on named entities, loaded into an `AnimationClip`
+#[derive(Deserialize)]
+struct TransformClip {
+    curves: Vec<TransformCurve>,
+}
+
+#[derive(Deserialize)]
+struct TransformCurve {
+    /// Names of the entities from the `AnimationPlayer` down to the animated one, starting with
+    /// the player's own
+    path: Vec<String>,
+    /// Seconds each keyframe is reached at
+    times: Vec<f32>,
+    keyframes: TransformKeyframes,
+}
+
+#[derive(Deserialize)]
+enum TransformKeyframes {
+    Translation(Vec<Vec3>),
+    /// Degrees anticlockwise around the z axis
+    Rotation(Vec<f32>),
+    Scale(Vec<Vec3>),
+}
+
+impl TransformKeyframes {
+    fn len(&self) -> usize {
+        match self {
+            TransformKeyframes::Translation(keyframes) | TransformKeyframes::Scale(keyframes) => {
+                keyframes.len()
+            }
+            TransformKeyframes::Rotation(keyframes) => keyframes.len(),
+        }
+    }
+}
+
+impl From<TransformKeyframes> for Keyframes {
+    fn from(keyframes: TransformKeyframes) -> Self {
+        match keyframes {
+            TransformKeyframes::Translation(translations) => Keyframes::Translation(translations),
+            TransformKeyframes::Rotation(angles) => Keyframes::Rotation(
+                angles
+                    .into_iter()
+                    .map(|angle| Quat::from_rotation_z(angle.to_radians()))
+                    .collect(),
+            ),
+            TransformKeyframes::Scale(scales) => Keyframes::Scale(scales),
+        }
+    }
+}
+
+/// Loads `anim.ron` transform clips, so animations can be shared between hierarchies using the
+/// same names and edited without recompiling
+#[derive(Default)]
+pub struct TransformClipLoader;
+
+#[derive(Debug, Error)]
+pub enum TransformClipLoaderError {
+    #[error("could not read animation clip: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse animation clip RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("curve on {path:?} has {times} times but {keyframes} keyframes")]
+    KeyframeCount {
+        path: Vec<String>,
+        times: usize,
+        keyframes: usize,
+    },
+    #[error("curve on {path:?} has times that go backwards")]
+    UnsortedTimes { path: Vec<String> },
+}
+
+impl AssetLoader for TransformClipLoader {
+    type Asset = AnimationClip;
+    type Settings = ();
+    type Error = TransformClipLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<AnimationClip, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let clip: TransformClip = ron::de::from_bytes(&bytes)?;
+
+            let mut animation = AnimationClip::default();
+            for curve in clip.curves {
+                if curve.times.len() != curve.keyframes.len() {
+                    return Err(TransformClipLoaderError::KeyframeCount {
+                        times: curve.times.len(),
+                        keyframes: curve.keyframes.len(),
+                        path: curve.path,
+                    });
+                }
+                if curve.times.windows(2).any(|pair| pair[1] < pair[0]) {
+                    return Err(TransformClipLoaderError::UnsortedTimes { path: curve.path });
+                }
+                animation.add_curve_to_path(
+                    EntityPath {
+                        parts: curve.path.into_iter().map(Name::new).collect(),
+                    },
+                    VariableCurve {
+                        keyframe_timestamps: curve.times,
+                        keyframes: curve.keyframes.into(),
+                    },
+                );
+            }
+            Ok(animation)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
@@ -1,33 +1,96 @@
 use crate::prelude::*;
 
+const XP_BAR_HEIGHT: f32 = 16.0;
+const XP_BAR_COLOUR: Color = Color::CYAN;
+const DASH_BAR_WIDTH: f32 = 120.0;
+const DASH_READY_COLOUR: Color = Color::WHITE;
+const DASH_COOLDOWN_COLOUR: Color = Color::GRAY;
+const SLOT_SIZE: f32 = 22.0;
+const EMPTY_SLOT_COLOUR: Color = Color::rgba(1.0, 1.0, 1.0, 0.1);
+const BOSS_BAR_WIDTH: f32 = 600.0;
+const BOSS_BAR_COLOUR: Color = Color::rgb(0.85, 0.1, 0.15);
+const SLAM_COLOUR: Color = Color::rgb(1.0, 0.3, 0.1);
+const BOSS_TELEGRAPH_COLOUR: Color = Color::rgb(1.0, 0.8, 0.2);
+
 #[derive(Component)]
 pub struct MobCountText;
 
+#[derive(Component)]
+pub struct XpBarFill;
+
+#[derive(Component)]
+pub struct LevelText;
+
+#[derive(Component)]
+pub struct RunTimeText;
+
+#[derive(Component)]
+pub struct DashBarFill;
+
+/// Health bar across the top of the screen, shown while a boss is alive
+#[derive(Component)]
+pub struct BossBar;
+
+#[derive(Component)]
+pub struct BossBarFill;
+
+#[derive(Component)]
+pub struct BossNameText;
+
+/// Weapon and passive slots shown next to the mob counter, rebuilt when the inventory changes
+#[derive(Component)]
+pub struct InventoryStrip;
+
 pub struct CustomUiPlugin;
 
 impl Plugin for CustomUiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup)
-            .add_systems(Update, update_mob_counter);
+        app.add_systems(OnExit(AppState::MainMenu), setup)
+            .add_systems(Update, update_mob_counter)
+            .add_systems(Update, update_xp_bar)
+            .add_systems(Update, update_run_time)
+            .add_systems(Update, update_dash_bar)
+            .add_systems(Update, update_inventory_strip)
+            .add_systems(Update, update_boss_bar)
+            .add_systems(Update, draw_target_line)
+            .add_systems(Update, draw_boss_telegraphs);
     }
 }
 
 pub fn setup(mut commands: Commands) {
     let root = commands
-        .spawn(NodeBundle {
-            background_color: BackgroundColor(Color::BLACK.with_a(0.3)),
-            z_index: ZIndex::Global(i32::MAX),
-            style: Style {
-                position_type: PositionType::Absolute,
-                bottom: Val::Px(5.),
-                right: Val::Px(5.),
-                top: Val::Auto,
-                left: Val::Auto,
-                padding: UiRect::all(Val::Px(4.0)),
+        .spawn((
+            RunEntity,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.3)),
+                z_index: ZIndex::Global(i32::MAX),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(5.),
+                    right: Val::Px(5.),
+                    top: Val::Auto,
+                    left: Val::Auto,
+                    padding: UiRect::all(Val::Px(4.0)),
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(12.),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        })
+        ))
+        .id();
+    let inventory_strip = commands
+        .spawn((
+            InventoryStrip,
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(2.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
         .id();
     let mob_count_text = commands
         .spawn((
@@ -55,7 +118,220 @@ pub fn setup(mut commands: Commands) {
             },
         ))
         .id();
-    commands.entity(root).push_children(&[mob_count_text]);
+    commands
+        .entity(root)
+        .push_children(&[inventory_strip, mob_count_text]);
+
+    let xp_bar = commands
+        .spawn((
+            RunEntity,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+                z_index: ZIndex::Global(i32::MAX),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.),
+                    left: Val::Px(0.),
+                    width: Val::Percent(100.),
+                    height: Val::Px(XP_BAR_HEIGHT),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let xp_bar_fill = commands
+        .spawn((
+            XpBarFill,
+            NodeBundle {
+                background_color: BackgroundColor(XP_BAR_COLOUR),
+                style: Style {
+                    width: Val::Percent(0.),
+                    height: Val::Percent(100.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let level_text = commands
+        .spawn((
+            LevelText,
+            TextBundle {
+                text: Text::from_section(
+                    "LV 1",
+                    TextStyle {
+                        font_size: 14.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(8.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    commands
+        .entity(xp_bar)
+        .push_children(&[xp_bar_fill, level_text]);
+
+    let run_time_root = commands
+        .spawn((
+            RunEntity,
+            NodeBundle {
+                z_index: ZIndex::Global(i32::MAX),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(XP_BAR_HEIGHT + 4.),
+                    width: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let run_time_text = commands
+        .spawn((
+            RunTimeText,
+            TextBundle::from_section(
+                "00:00",
+                TextStyle {
+                    font_size: 32.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ),
+        ))
+        .id();
+    commands
+        .entity(run_time_root)
+        .push_children(&[run_time_text]);
+
+    let dash_root = commands
+        .spawn((
+            RunEntity,
+            NodeBundle {
+                z_index: ZIndex::Global(i32::MAX),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(24.),
+                    width: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(4.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let dash_text = commands
+        .spawn(TextBundle::from_section(
+            "DASH",
+            TextStyle {
+                font_size: 14.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        ))
+        .id();
+    let dash_bar = commands
+        .spawn(NodeBundle {
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            style: Style {
+                width: Val::Px(DASH_BAR_WIDTH),
+                height: Val::Px(6.),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    let dash_bar_fill = commands
+        .spawn((
+            DashBarFill,
+            NodeBundle {
+                background_color: BackgroundColor(DASH_READY_COLOUR),
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    commands.entity(dash_bar).push_children(&[dash_bar_fill]);
+    commands
+        .entity(dash_root)
+        .push_children(&[dash_text, dash_bar]);
+
+    let boss_root = commands
+        .spawn((
+            RunEntity,
+            BossBar,
+            NodeBundle {
+                z_index: ZIndex::Global(i32::MAX),
+                visibility: Visibility::Hidden,
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(XP_BAR_HEIGHT + 48.),
+                    width: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(4.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let boss_name_text = commands
+        .spawn((
+            BossNameText,
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ),
+        ))
+        .id();
+    let boss_bar = commands
+        .spawn(NodeBundle {
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            style: Style {
+                width: Val::Px(BOSS_BAR_WIDTH),
+                height: Val::Px(14.),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    let boss_bar_fill = commands
+        .spawn((
+            BossBarFill,
+            NodeBundle {
+                background_color: BackgroundColor(BOSS_BAR_COLOUR),
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    commands.entity(boss_bar).push_children(&[boss_bar_fill]);
+    commands
+        .entity(boss_root)
+        .push_children(&[boss_name_text, boss_bar]);
 }
 
 pub fn update_mob_counter(counter: Res<MobCount>, mut query: Query<&mut Text, With<MobCountText>>) {
@@ -64,3 +340,198 @@ pub fn update_mob_counter(counter: Res<MobCount>, mut query: Query<&mut Text, Wi
         text.sections[1].value = format!("{value:>4.0}");
     }
 }
+
+pub fn update_xp_bar(
+    player_query: Query<&Experience, With<Player>>,
+    mut fill_query: Query<&mut Style, With<XpBarFill>>,
+    mut text_query: Query<&mut Text, With<LevelText>>,
+) {
+    if let Ok(experience) = player_query.get_single() {
+        if let Ok(mut style) = fill_query.get_single_mut() {
+            style.width = Val::Percent(experience.progress() * 100.);
+        }
+        if let Ok(mut text) = text_query.get_single_mut() {
+            text.sections[0].value = format!("LV {}", experience.level);
+        }
+    }
+}
+
+pub fn update_run_time(
+    director: Res<WaveDirector>,
+    mut query: Query<&mut Text, With<RunTimeText>>,
+) {
+    if let Ok(mut text) = query.get_single_mut() {
+        let seconds = director.elapsed as u32;
+        text.sections[0].value = format!("{:02}:{:02}", seconds / 60, seconds % 60);
+    }
+}
+
+pub fn update_dash_bar(
+    player_query: Query<&Dash, With<Player>>,
+    mut fill_query: Query<(&mut Style, &mut BackgroundColor), With<DashBarFill>>,
+) {
+    if let (Ok(dash), Ok((mut style, mut colour))) =
+        (player_query.get_single(), fill_query.get_single_mut())
+    {
+        style.width = Val::Percent(dash.cooldown.percent() * 100.);
+        colour.0 = if dash.cooldown.finished() {
+            DASH_READY_COLOUR
+        } else {
+            DASH_COOLDOWN_COLOUR
+        };
+    }
+}
+
+/// Colour and level of each filled slot, in order
+type SlotRow = Vec<(Color, u32)>;
+
+pub fn update_inventory_strip(
+    mut commands: Commands,
+    data: Res<DataAssets>,
+    passive_catalogues: Res<Assets<PassiveCatalogue>>,
+    player_query: Query<(&Inventory, &Children), With<Player>>,
+    weapon_query: Query<&Weapon>,
+    strip_query: Query<Entity, With<InventoryStrip>>,
+    mut shown: Local<Option<(SlotRow, SlotRow)>>,
+) {
+    let (Ok((inventory, children)), Ok(strip)) =
+        (player_query.get_single(), strip_query.get_single())
+    else {
+        return;
+    };
+    let passives = passive_catalogues.get(&data.passives);
+
+    let weapons: SlotRow = children
+        .iter()
+        .filter_map(|child| weapon_query.get(*child).ok())
+        .map(|weapon| (weapon.colour, weapon.level))
+        .collect();
+    let passive_slots: SlotRow = inventory
+        .passives
+        .iter()
+        .map(|passive| {
+            let colour = passives
+                .and_then(|passives| passives.get(&passive.id))
+                .map_or(Color::WHITE, PassiveDef::colour);
+            (colour, passive.level)
+        })
+        .collect();
+    let slots = (weapons, passive_slots);
+    if shown.as_ref() == Some(&slots) {
+        return;
+    }
+
+    commands.entity(strip).despawn_descendants();
+    for (row, capacity) in [
+        (&slots.0, inventory.weapon_slots),
+        (&slots.1, inventory.passive_slots),
+    ] {
+        let row_node = commands
+            .spawn(NodeBundle {
+                style: Style {
+                    column_gap: Val::Px(2.),
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
+        for index in 0..capacity {
+            let (colour, level) = row.get(index).copied().unwrap_or((EMPTY_SLOT_COLOUR, 0));
+            let slot = commands
+                .spawn(NodeBundle {
+                    background_color: BackgroundColor(colour),
+                    style: Style {
+                        width: Val::Px(SLOT_SIZE),
+                        height: Val::Px(SLOT_SIZE),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            if level > 0 {
+                let text = commands
+                    .spawn(TextBundle::from_section(
+                        level.to_string(),
+                        TextStyle {
+                            font_size: 14.0,
+                            color: Color::BLACK,
+                            ..default()
+                        },
+                    ))
+                    .id();
+                commands.entity(slot).push_children(&[text]);
+            }
+            commands.entity(row_node).push_children(&[slot]);
+        }
+        commands.entity(strip).push_children(&[row_node]);
+    }
+    *shown = Some(slots);
+}
+
+/// Tracks the first boss alive, naming its phase when it has more than one
+pub fn update_boss_bar(
+    boss_query: Query<(&Health, &Name, Option<&BossScript>), With<Boss>>,
+    mut bar_query: Query<&mut Visibility, With<BossBar>>,
+    mut fill_query: Query<&mut Style, With<BossBarFill>>,
+    mut text_query: Query<&mut Text, With<BossNameText>>,
+) {
+    let boss = boss_query.iter().next();
+    for mut visibility in &mut bar_query {
+        *visibility = if boss.is_some() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    let Some((health, name, script)) = boss else {
+        return;
+    };
+
+    if let Ok(mut style) = fill_query.get_single_mut() {
+        style.width = Val::Percent(health.current / health.max.max(f32::EPSILON) * 100.);
+    }
+    if let Ok(mut text) = text_query.get_single_mut() {
+        text.sections[0].value = match script {
+            Some(script) if script.phases.len() > 1 => {
+                format!("{name} - Phase {}", script.phase + 1)
+            }
+            _ => name.to_string(),
+        };
+    }
+}
+
+pub fn draw_target_line(
+    mut gizmos: Gizmos,
+    target_query: Query<&Transform, With<NearestMob>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if let (Ok(player), Ok(target)) = (player_query.get_single(), target_query.get_single()) {
+        gizmos.line_2d(player.translation.xy(), target.translation.xy(), Color::RED);
+    }
+}
+
+/// Slam markers fill in as they count down, and a ring closes in on bosses winding up an attack
+pub fn draw_boss_telegraphs(
+    mut gizmos: Gizmos,
+    marker_query: Query<(&SlamMarker, &Transform)>,
+    boss_query: Query<(&BossScript, &Transform, &Sprite)>,
+) {
+    for (marker, transform) in marker_query.iter() {
+        let centre = transform.translation.xy();
+        gizmos.circle_2d(centre, marker.radius, SLAM_COLOUR);
+        gizmos.circle_2d(centre, marker.radius * marker.timer.percent(), SLAM_COLOUR);
+    }
+    for (script, transform, sprite) in boss_query.iter() {
+        let Some(progress) = script.telegraph_progress() else {
+            continue;
+        };
+        let size = sprite.custom_size.map_or(0.0, |size| size.x);
+        gizmos.circle_2d(
+            transform.translation.xy(),
+            size * (1.0 - progress * 0.5),
+            BOSS_TELEGRAPH_COLOUR,
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use crate::prelude::*;
+
+use bevy::reflect::TypePath;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::Deserialize;
+
+const CHOICE_COUNT: usize = 3;
+const BUTTON_COLOUR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_HIGHLIGHT_COLOUR: Color = Color::rgb(0.25, 0.25, 0.25);
+
+/// Weighted pool the level-up choices are drawn from
+#[derive(Asset, TypePath, Deserialize)]
+pub struct UpgradeCatalogue {
+    /// Weight of offering each weapon the player does not own yet
+    pub new_weapon_weight: f32,
+    /// Weight of offering the next level of each weapon the player owns
+    pub weapon_level_weight: f32,
+    /// Weight of offering each passive the player does not hold yet
+    pub new_passive_weight: f32,
+    /// Weight of offering the next level of each passive the player holds
+    pub passive_level_weight: f32,
+    pub stat_boosts: Vec<StatBoost>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct StatBoost {
+    pub name: String,
+    pub description: String,
+    pub boost: Boost,
+    pub weight: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Boost {
+    /// Puts a modifier on one of the player's stats for the rest of the run
+    Stat(Stat, Modifier),
+    /// Heals this much health
+    Heal(f32),
+}
+
+#[derive(Clone)]
+pub enum Upgrade {
+    NewWeapon(String),
+    /// Weapon id and the level it is raised to
+    WeaponLevel(String, u32),
+    NewPassive(String),
+    /// Passive id and the level it is raised to
+    PassiveLevel(String, u32),
+    Stat(StatBoost),
+}
+
+impl Upgrade {
+    pub fn label(
+        &self,
+        weapons: &WeaponCatalogue,
+        passives: &PassiveCatalogue,
+    ) -> (String, String) {
+        match self {
+            Upgrade::NewWeapon(id) => (weapon_name(weapons, id), "New weapon".into()),
+            Upgrade::WeaponLevel(id, level) => (weapon_name(weapons, id), format!("Level {level}")),
+            Upgrade::NewPassive(id) => (passive_name(passives, id), "New passive".into()),
+            Upgrade::PassiveLevel(id, level) => {
+                (passive_name(passives, id), format!("Level {level}"))
+            }
+            Upgrade::Stat(boost) => (boost.name.clone(), boost.description.clone()),
+        }
+    }
+}
+
+fn weapon_name(weapons: &WeaponCatalogue, id: &str) -> String {
+    weapons
+        .get(id)
+        .map(|def| def.name.clone())
+        .unwrap_or_else(|| id.to_string())
+}
+
+fn passive_name(passives: &PassiveCatalogue, id: &str) -> String {
+    passives
+        .get(id)
+        .map(|def| def.name.clone())
+        .unwrap_or_else(|| id.to_string())
+}
+
+#[derive(Resource)]
+pub struct UpgradeChoices(pub Vec<Upgrade>);
+
+#[derive(Component)]
+pub struct UpgradeButton(pub usize);
+
+/// Index into `UpgradeChoices` that `Confirm` picks, moved with the previous and next choice
+/// actions or by hovering a choice
+#[derive(Resource, Default)]
+pub struct HighlightedUpgrade(pub usize);
+
+/// Index into `UpgradeChoices` the player picked
+#[derive(Event)]
+pub struct UpgradeChosen(pub usize);
+
+pub struct UpgradePlugin;
+
+impl Plugin for UpgradePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<UpgradeCatalogue>()
+            .register_asset_loader(RonAssetLoader::<UpgradeCatalogue>::new(&["upgrades.ron"]))
+            .add_event::<UpgradeChosen>()
+            .add_systems(OnEnter(AppState::LevelUp), offer_upgrades)
+            .add_systems(
+                Update,
+                (
+                    move_upgrade_highlight,
+                    highlight_upgrade_buttons,
+                    choose_upgrade,
+                )
+                    .chain()
+                    .run_if(resource_exists::<ActionState>())
+                    .run_if(not(resource_exists::<ReplayPlayback>()))
+                    .run_if(in_state(AppState::LevelUp)),
+            )
+            .add_systems(
+                FixedUpdate,
+                apply_upgrade
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::LevelUp)),
+            )
+            .add_systems(OnExit(AppState::LevelUp), clear_upgrade_choices);
+    }
+}
+
+/// Draws up to `count` distinct upgrades, weighted by the catalogue. New weapons and passives
+/// are only offered while there is a free slot for them.
+#[allow(clippy::too_many_arguments)]
+pub fn roll_upgrades(
+    upgrades: &UpgradeCatalogue,
+    weapons: &WeaponCatalogue,
+    passives: &PassiveCatalogue,
+    owned: &[&Weapon],
+    inventory: &Inventory,
+    count: usize,
+    rng: &mut impl Rng,
+) -> Vec<Upgrade> {
+    let mut candidates: Vec<(Upgrade, f32)> = Vec::new();
+    for def in weapons.weapons.iter() {
+        match owned.iter().find(|weapon| weapon.id == def.id) {
+            Some(weapon) if weapon.level < def.max_level() => candidates.push((
+                Upgrade::WeaponLevel(def.id.clone(), weapon.level + 1),
+                upgrades.weapon_level_weight,
+            )),
+            Some(_) => {}
+            // Weapons that already evolved are not offered again
+            None if owned.len() < inventory.weapon_slots
+                && !weapons.is_evolution(&def.id)
+                && !def.evolution.as_ref().is_some_and(|evolution| {
+                    owned.iter().any(|weapon| weapon.id == evolution.into)
+                }) =>
+            {
+                candidates.push((
+                    Upgrade::NewWeapon(def.id.clone()),
+                    upgrades.new_weapon_weight,
+                ))
+            }
+            None => {}
+        }
+    }
+    for def in passives.passives.iter() {
+        match inventory.passive(&def.id) {
+            Some(passive) if passive.level < def.max_level() => candidates.push((
+                Upgrade::PassiveLevel(def.id.clone(), passive.level + 1),
+                upgrades.passive_level_weight,
+            )),
+            Some(_) => {}
+            None if inventory.has_free_passive_slot() => candidates.push((
+                Upgrade::NewPassive(def.id.clone()),
+                upgrades.new_passive_weight,
+            )),
+            None => {}
+        }
+    }
+    for boost in upgrades.stat_boosts.iter() {
+        candidates.push((Upgrade::Stat(boost.clone()), boost.weight));
+    }
+    candidates.retain(|(_, weight)| *weight > 0.0);
+
+    // Several upgrades drawn at once, as chests do, must not need more slots than are free
+    let mut free_weapon_slots = inventory.weapon_slots.saturating_sub(owned.len());
+    let mut free_passive_slots = inventory
+        .passive_slots
+        .saturating_sub(inventory.passives.len());
+    let mut choices = Vec::with_capacity(count);
+    while choices.len() < count && !candidates.is_empty() {
+        let Ok(index) = WeightedIndex::new(candidates.iter().map(|(_, weight)| *weight)) else {
+            break;
+        };
+        let upgrade = candidates.swap_remove(index.sample(rng)).0;
+        match upgrade {
+            Upgrade::NewWeapon(_) => {
+                free_weapon_slots = free_weapon_slots.saturating_sub(1);
+                if free_weapon_slots == 0 {
+                    candidates.retain(|(upgrade, _)| !matches!(upgrade, Upgrade::NewWeapon(_)));
+                }
+            }
+            Upgrade::NewPassive(_) => {
+                free_passive_slots = free_passive_slots.saturating_sub(1);
+                if free_passive_slots == 0 {
+                    candidates.retain(|(upgrade, _)| !matches!(upgrade, Upgrade::NewPassive(_)));
+                }
+            }
+            _ => {}
+        }
+        choices.push(upgrade);
+    }
+    choices
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn offer_upgrades(
+    mut commands: Commands,
+    data: Res<DataAssets>,
+    upgrade_catalogues: Res<Assets<UpgradeCatalogue>>,
+    weapon_catalogues: Res<Assets<WeaponCatalogue>>,
+    passive_catalogues: Res<Assets<PassiveCatalogue>>,
+    mut rng: ResMut<GameRng>,
+    weapon_query: Query<&Weapon>,
+    player_query: Query<(&Stats, &Inventory), With<Player>>,
+) {
+    commands.insert_resource(HighlightedUpgrade::default());
+    let (Some(upgrades), Some(weapons), Some(passives), Ok((stats, inventory))) = (
+        upgrade_catalogues.get(&data.upgrades),
+        weapon_catalogues.get(&data.weapons),
+        passive_catalogues.get(&data.passives),
+        player_query.get_single(),
+    ) else {
+        commands.insert_resource(UpgradeChoices(Vec::new()));
+        return;
+    };
+
+    let rng = rng.stream("upgrades");
+    let luck = stats.get(Stat::Luck);
+    let lucky = luck > 0.0 && rng.gen_bool(luck.min(1.0) as f64);
+    let owned: Vec<&Weapon> = weapon_query.iter().collect();
+    let choices = roll_upgrades(
+        upgrades,
+        weapons,
+        passives,
+        &owned,
+        inventory,
+        CHOICE_COUNT + usize::from(lucky),
+        rng,
+    );
+
+    let root = commands
+        .spawn((
+            DespawnOnExit(AppState::LevelUp),
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.6)),
+                z_index: ZIndex::Global(i32::MAX - 1),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(24.),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    let title = commands
+        .spawn(TextBundle::from_section(
+            "Level Up!",
+            TextStyle {
+                font_size: 48.0,
+                color: Color::YELLOW,
+                ..default()
+            },
+        ))
+        .id();
+    let row = commands
+        .spawn(NodeBundle {
+            style: Style {
+                column_gap: Val::Px(16.),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    commands.entity(root).push_children(&[title, row]);
+
+    for (index, upgrade) in choices.iter().enumerate() {
+        let (name, description) = upgrade.label(weapons, passives);
+        let button = commands
+            .spawn((
+                UpgradeButton(index),
+                ButtonBundle {
+                    background_color: BackgroundColor(BUTTON_COLOUR),
+                    style: Style {
+                        width: Val::Px(260.),
+                        padding: UiRect::all(Val::Px(12.)),
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(8.),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .id();
+        let text = commands
+            .spawn(TextBundle::from_sections([
+                TextSection {
+                    value: format!("{name}\n"),
+                    style: TextStyle {
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                },
+                TextSection {
+                    value: description,
+                    style: TextStyle {
+                        font_size: 18.0,
+                        color: Color::GRAY,
+                        ..default()
+                    },
+                },
+            ]))
+            .id();
+        commands.entity(button).push_children(&[text]);
+        commands.entity(row).push_children(&[button]);
+    }
+
+    commands.insert_resource(UpgradeChoices(choices));
+}
+
+/// Steps the highlight through the choices, wrapping around at either end
+pub fn move_upgrade_highlight(
+    actions: Res<ActionState>,
+    choices: Res<UpgradeChoices>,
+    mut highlighted: ResMut<HighlightedUpgrade>,
+) {
+    let count = choices.0.len();
+    if count == 0 {
+        return;
+    }
+    if actions.just_pressed(Action::PreviousChoice) {
+        highlighted.0 = (highlighted.0 + count - 1) % count;
+    }
+    if actions.just_pressed(Action::NextChoice) {
+        highlighted.0 = (highlighted.0 + 1) % count;
+    }
+}
+
+pub fn highlight_upgrade_buttons(
+    interaction_query: Query<(&Interaction, &UpgradeButton), Changed<Interaction>>,
+    mut button_query: Query<(&UpgradeButton, &mut BackgroundColor)>,
+    mut highlighted: ResMut<HighlightedUpgrade>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Hovered {
+            highlighted.0 = button.0;
+        }
+    }
+    if !highlighted.is_changed() {
+        return;
+    }
+    for (button, mut colour) in &mut button_query {
+        colour.0 = if button.0 == highlighted.0 {
+            BUTTON_HIGHLIGHT_COLOUR
+        } else {
+            BUTTON_COLOUR
+        };
+    }
+}
+
+pub fn choose_upgrade(
+    actions: Res<ActionState>,
+    highlighted: Res<HighlightedUpgrade>,
+    button_query: Query<(&Interaction, &UpgradeButton), Changed<Interaction>>,
+    mut chosen_events: EventWriter<UpgradeChosen>,
+) {
+    let pressed = button_query
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, button)| button.0)
+        .or_else(|| {
+            actions
+                .just_pressed(Action::Confirm)
+                .then_some(highlighted.0)
+        });
+
+    if let Some(index) = pressed {
+        chosen_events.send(UpgradeChosen(index));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn apply_upgrade(
+    mut commands: Commands,
+    mut chosen_events: EventReader<UpgradeChosen>,
+    choices: Res<UpgradeChoices>,
+    data: Res<DataAssets>,
+    weapon_catalogues: Res<Assets<WeaponCatalogue>>,
+    passive_catalogues: Res<Assets<PassiveCatalogue>>,
+    mut player_query: Query<(Entity, &mut Health, &mut Stats, &mut Experience), With<Player>>,
+    mut inventory_query: Query<&mut Inventory>,
+    mut weapon_query: Query<&mut Weapon>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Ok((player, mut health, mut stats, mut experience)) = player_query.get_single_mut() else {
+        next_state.set(AppState::Playing);
+        return;
+    };
+
+    // Choices that were not offered are ignored
+    let chosen = chosen_events
+        .read()
+        .find_map(|chosen| choices.0.get(chosen.0));
+    // Nothing left to offer, so skip straight back to the run
+    if chosen.is_none() && !choices.0.is_empty() {
+        return;
+    }
+
+    if let (Some(upgrade), Ok(mut inventory)) = (chosen, inventory_query.get_mut(player)) {
+        grant_upgrade(
+            &mut commands,
+            upgrade,
+            player,
+            &mut health,
+            &mut stats,
+            &mut inventory,
+            &mut weapon_query,
+            weapon_catalogues.get(&data.weapons),
+            passive_catalogues.get(&data.passives),
+        );
+    }
+
+    experience.pending_level_ups = experience.pending_level_ups.saturating_sub(1);
+    next_state.set(AppState::Playing);
+}
+
+/// Gives `upgrade` to the player, whether it was picked on level up or found in a chest
+#[allow(clippy::too_many_arguments)]
+pub fn grant_upgrade(
+    commands: &mut Commands,
+    upgrade: &Upgrade,
+    player: Entity,
+    health: &mut Health,
+    stats: &mut Stats,
+    inventory: &mut Inventory,
+    weapon_query: &mut Query<&mut Weapon>,
+    weapons: Option<&WeaponCatalogue>,
+    passives: Option<&PassiveCatalogue>,
+) {
+    match upgrade {
+        Upgrade::NewWeapon(id) => {
+            if let Some(def) = weapons.and_then(|weapons| weapons.get(id)) {
+                spawn_weapon(commands, player, stats, def, 1);
+            }
+        }
+        Upgrade::WeaponLevel(id, level) => {
+            if let Some(def) = weapons.and_then(|weapons| weapons.get(id)) {
+                for mut weapon in weapon_query.iter_mut() {
+                    if weapon.id == *id {
+                        weapon.set_level(def, *level, stats);
+                    }
+                }
+            }
+        }
+        Upgrade::NewPassive(id) => {
+            if let Some(def) = passives.and_then(|passives| passives.get(id)) {
+                inventory.equip_passive(stats, def, 1);
+            }
+        }
+        Upgrade::PassiveLevel(id, level) => {
+            if let Some(def) = passives.and_then(|passives| passives.get(id)) {
+                inventory.equip_passive(stats, def, *level);
+            }
+        }
+        Upgrade::Stat(boost) => match boost.boost {
+            Boost::Stat(stat, modifier) => {
+                stats.add_modifier(ModifierSource::Upgrade, stat, modifier);
+            }
+            Boost::Heal(amount) => health.current = (health.current + amount).min(health.max),
+        },
+    }
+}
+
+pub fn clear_upgrade_choices(mut commands: Commands) {
+    commands.remove_resource::<UpgradeChoices>();
+    commands.remove_resource::<HighlightedUpgrade>();
+}
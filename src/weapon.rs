@@ -0,0 +1,394 @@
+use crate::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use bevy::reflect::TypePath;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Most of a weapon's cooldown that stats can take off
+const MAX_COOLDOWN_REDUCTION: f32 = 0.8;
+
+#[derive(Asset, TypePath, Deserialize)]
+pub struct WeaponCatalogue {
+    /// Id of the weapon the player starts every run with
+    pub starting_weapon: String,
+    pub weapons: Vec<WeaponDef>,
+}
+
+impl WeaponCatalogue {
+    pub fn get(&self, id: &str) -> Option<&WeaponDef> {
+        self.weapons.iter().find(|weapon| weapon.id == id)
+    }
+
+    /// Whether `id` is only obtained by evolving another weapon
+    pub fn is_evolution(&self, id: &str) -> bool {
+        self.weapons.iter().any(|weapon| {
+            weapon
+                .evolution
+                .as_ref()
+                .is_some_and(|evolution| evolution.into == id)
+        })
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WeaponDef {
+    pub id: String,
+    pub name: String,
+    pub kind: WeaponKind,
+    pub colour: (f32, f32, f32),
+    /// Stats for each level, starting at level 1
+    pub levels: Vec<WeaponStats>,
+    #[serde(default)]
+    pub evolution: Option<Evolution>,
+}
+
+/// Weapon a max level weapon turns into while the player holds `passive`
+#[derive(Clone, Deserialize)]
+pub struct Evolution {
+    pub passive: String,
+    pub into: String,
+}
+
+impl WeaponDef {
+    pub fn max_level(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    pub fn stats(&self, level: u32) -> WeaponStats {
+        let index = level.clamp(1, self.max_level().max(1)) - 1;
+        self.levels.get(index as usize).copied().unwrap_or_default()
+    }
+
+    pub fn colour(&self) -> Color {
+        Color::rgb(self.colour.0, self.colour.1, self.colour.2)
+    }
+}
+
+/// Firing pattern of a weapon, each handled by its own system
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum WeaponKind {
+    /// Shoots at the nearest mob
+    Projectile,
+    /// Blades circling the player
+    Orbit,
+    /// Projectiles fired evenly in every direction
+    Burst,
+    /// Beam hitting every mob along its length
+    Laser,
+    /// Pulses damage to every mob within a radius of the player
+    Aura,
+    /// Missiles that steer towards the closest mob
+    Homing,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct WeaponStats {
+    pub damage: f32,
+    /// Seconds between activations
+    pub cooldown: f32,
+    /// Projectiles, blades or missiles per activation
+    pub count: u32,
+    /// Projectile speed, or radians per second for orbiting blades
+    pub speed: f32,
+    /// Number of extra mobs a projectile can pass through before it is used up
+    pub pierce: u32,
+    /// Seconds a projectile, blade or beam stays alive
+    pub duration: f32,
+    /// Projectile diameter or beam width
+    pub size: f32,
+    /// Orbit radius, aura radius or beam length
+    pub area: f32,
+    /// Radians per second a homing missile can turn
+    pub turn_rate: f32,
+    /// Impulse pushing a hit mob away from the player
+    pub knockback: f32,
+    /// Effect put on every mob hit
+    pub effect: Option<StatusEffect>,
+}
+
+impl Default for WeaponStats {
+    fn default() -> Self {
+        Self {
+            damage: 10.0,
+            cooldown: 1.0,
+            count: 1,
+            speed: 600.0,
+            pierce: 0,
+            duration: 2.0,
+            size: 16.0,
+            area: 100.0,
+            turn_rate: 4.0,
+            knockback: 150.0,
+            effect: None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Weapon {
+    pub id: String,
+    pub kind: WeaponKind,
+    pub level: u32,
+    /// Stats of the current level, as listed in the catalogue
+    pub base: WeaponStats,
+    /// `base` with the owner's stats applied, used when firing
+    pub stats: WeaponStats,
+    pub colour: Color,
+    pub cooldown: Timer,
+}
+
+impl Weapon {
+    pub fn new(def: &WeaponDef, level: u32, owner: &Stats) -> Self {
+        let base = def.stats(level);
+        let mut weapon = Self {
+            id: def.id.clone(),
+            kind: def.kind,
+            level,
+            base,
+            stats: base,
+            colour: def.colour(),
+            cooldown: Timer::from_seconds(base.cooldown, TimerMode::Once),
+        };
+        weapon.rescale(owner);
+        weapon
+    }
+
+    pub fn set_level(&mut self, def: &WeaponDef, level: u32, owner: &Stats) {
+        self.level = level;
+        self.base = def.stats(level);
+        self.rescale(owner);
+    }
+
+    /// Works `stats` out again from `base` and the owner's area and cooldown reduction
+    pub fn rescale(&mut self, owner: &Stats) {
+        let area = owner.get(Stat::Area);
+        let reduction = owner
+            .get(Stat::CooldownReduction)
+            .clamp(0.0, MAX_COOLDOWN_REDUCTION);
+        self.stats = WeaponStats {
+            area: self.base.area * area,
+            size: self.base.size * area,
+            cooldown: self.base.cooldown * (1.0 - reduction),
+            ..self.base
+        };
+        self.cooldown
+            .set_duration(Duration::from_secs_f32(self.stats.cooldown));
+    }
+}
+
+/// Marks a player that has been given its starting weapon
+#[derive(Component)]
+pub struct Armed;
+
+#[derive(Component)]
+pub struct Projectile {
+    pub damage: f32,
+    pub knockback: f32,
+    pub effect: Option<StatusEffect>,
+    pub hits_remaining: u32,
+}
+
+#[derive(Component, Deref, DerefMut)]
+pub struct Lifetime(pub Timer);
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<WeaponCatalogue>()
+            .register_asset_loader(RonAssetLoader::<WeaponCatalogue>::new(&["weapons.ron"]))
+            .add_systems(
+                FixedUpdate,
+                (
+                    equip_starting_weapon,
+                    rescale_weapons,
+                    tick_weapon_cooldowns,
+                    (
+                        fire_projectiles,
+                        fire_bursts,
+                        fire_homing,
+                        fire_lasers,
+                        spawn_orbiters,
+                        pulse_auras.before(apply_damage),
+                    ),
+                )
+                    .chain()
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    steer_homing,
+                    move_orbiters,
+                    projectile_hits.before(apply_damage),
+                    despawn_expired,
+                )
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (spawn_aura_visuals, update_aura_visuals).run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+pub fn spawn_weapon(
+    commands: &mut Commands,
+    owner: Entity,
+    stats: &Stats,
+    def: &WeaponDef,
+    level: u32,
+) -> Entity {
+    let weapon = commands
+        .spawn((
+            SpatialBundle::default(),
+            Name::new(def.name.clone()),
+            Weapon::new(def, level, stats),
+        ))
+        .id();
+    commands.entity(owner).add_child(weapon);
+    weapon
+}
+
+/// Spawns a sensor moving in `direction` that damages the mobs it touches
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    weapon: &Weapon,
+    origin: Vec2,
+    direction: Vec2,
+) -> Entity {
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: weapon.colour,
+                    custom_size: Some(Vec2::splat(weapon.stats.size)),
+                    ..default()
+                },
+                transform: Transform::from_translation(origin.extend(0.5))
+                    .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x))),
+                ..default()
+            },
+            Name::new("Projectile"),
+            RigidBody::KinematicVelocityBased,
+            Collider::ball(weapon.stats.size / 2.),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            Velocity::linear(direction * weapon.stats.speed),
+            Projectile {
+                damage: weapon.stats.damage,
+                knockback: weapon.stats.knockback,
+                effect: weapon.stats.effect,
+                hits_remaining: weapon.stats.pierce + 1,
+            },
+            Lifetime(Timer::from_seconds(weapon.stats.duration, TimerMode::Once)),
+            RunEntity,
+        ))
+        .id()
+}
+
+pub fn equip_starting_weapon(
+    mut commands: Commands,
+    data: Res<DataAssets>,
+    catalogues: Res<Assets<WeaponCatalogue>>,
+    player_query: Query<Entity, (With<Player>, Without<Armed>)>,
+    stats_query: Query<&Stats>,
+) {
+    if let Ok(player) = player_query.get_single() {
+        let Ok(stats) = stats_query.get(player) else {
+            return;
+        };
+        if let Some(catalogue) = catalogues.get(&data.weapons) {
+            match catalogue.get(&catalogue.starting_weapon) {
+                Some(def) => {
+                    spawn_weapon(&mut commands, player, stats, def, 1);
+                }
+                None => error!(
+                    "Starting weapon {:?} is not in the weapon catalogue",
+                    catalogue.starting_weapon
+                ),
+            }
+            commands.entity(player).insert(Armed);
+        }
+    }
+}
+
+/// Keeps weapons in line with the player's area and cooldown reduction as they change
+pub fn rescale_weapons(
+    player_query: Query<&Stats, (With<Player>, Changed<Stats>)>,
+    mut weapon_query: Query<&mut Weapon>,
+) {
+    if let Ok(stats) = player_query.get_single() {
+        for mut weapon in &mut weapon_query {
+            weapon.rescale(stats);
+        }
+    }
+}
+
+pub fn tick_weapon_cooldowns(time: Res<Time>, mut weapon_query: Query<&mut Weapon>) {
+    for mut weapon in &mut weapon_query {
+        weapon.cooldown.tick(time.delta());
+    }
+}
+
+pub fn projectile_hits(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut status_events: EventWriter<ApplyStatus>,
+    mut projectile_query: Query<&mut Projectile>,
+    mob_query: Query<&Transform, With<Mob>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let player_position = player_query
+        .get_single()
+        .map_or(Vec2::ZERO, |player| player.translation.xy());
+    for event in collision_events.read() {
+        if let CollisionEvent::Started(a, b, _) = event {
+            for (projectile_entity, target) in [(*a, *b), (*b, *a)] {
+                let Ok(mob) = mob_query.get(target) else {
+                    continue;
+                };
+                if let Ok(mut projectile) = projectile_query.get_mut(projectile_entity) {
+                    // Already used up and waiting to be despawned
+                    if projectile.hits_remaining == 0 {
+                        continue;
+                    }
+
+                    let away = (mob.translation.xy() - player_position).normalize_or_zero();
+                    damage_events.send(DamageEvent {
+                        target,
+                        amount: projectile.damage,
+                        knockback: away * projectile.knockback,
+                        over_time: false,
+                    });
+                    if let Some(effect) = projectile.effect {
+                        status_events.send(ApplyStatus { target, effect });
+                    }
+
+                    projectile.hits_remaining -= 1;
+                    if projectile.hits_remaining == 0 {
+                        commands.entity(projectile_entity).despawn_recursive();
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn despawn_expired(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Lifetime)>,
+) {
+    for (entity, mut lifetime) in &mut query {
+        lifetime.tick(time.delta());
+        if lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
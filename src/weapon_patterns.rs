@@ -0,0 +1,321 @@
+use crate::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use std::f32::consts::TAU;
+
+/// Angle between projectiles when a weapon fires more than one at a target
+const PROJECTILE_SPREAD: f32 = 0.17;
+const HOMING_SPREAD: f32 = 0.5;
+const AURA_OPACITY: f32 = 0.15;
+
+#[derive(Component)]
+pub struct Homing {
+    pub turn_rate: f32,
+}
+
+#[derive(Component)]
+pub struct Orbiter {
+    pub owner: Entity,
+    pub angle: f32,
+    pub radius: f32,
+    pub angular_speed: f32,
+}
+
+#[derive(Component)]
+pub struct AuraVisual;
+
+/// Directions fanned out around `direction`, `spread` radians apart
+fn fan(direction: Vec2, count: u32, spread: f32) -> impl Iterator<Item = Vec2> {
+    let start = -spread * (count.saturating_sub(1) as f32) / 2.;
+    (0..count).map(move |i| Vec2::from_angle(start + spread * i as f32).rotate(direction))
+}
+
+pub fn fire_projectiles(
+    mut commands: Commands,
+    mut weapon_query: Query<(&GlobalTransform, &mut Weapon)>,
+    target_query: Query<&Transform, With<NearestMob>>,
+) {
+    let Ok(target) = target_query.get_single() else {
+        return;
+    };
+    for (transform, mut weapon) in &mut weapon_query {
+        if weapon.kind != WeaponKind::Projectile || !weapon.cooldown.finished() {
+            continue;
+        }
+
+        let origin = transform.translation().xy();
+        let direction = (target.translation.xy() - origin).normalize_or_zero();
+        if direction == Vec2::ZERO {
+            continue;
+        }
+
+        for direction in fan(direction, weapon.stats.count, PROJECTILE_SPREAD) {
+            spawn_projectile(&mut commands, &weapon, origin, direction);
+        }
+        weapon.cooldown.reset();
+    }
+}
+
+pub fn fire_bursts(
+    mut commands: Commands,
+    mut weapon_query: Query<(&GlobalTransform, &mut Weapon)>,
+) {
+    for (transform, mut weapon) in &mut weapon_query {
+        if weapon.kind != WeaponKind::Burst || !weapon.cooldown.finished() {
+            continue;
+        }
+
+        let origin = transform.translation().xy();
+        let count = weapon.stats.count.max(1);
+        for i in 0..count {
+            let direction = Vec2::from_angle(TAU * i as f32 / count as f32);
+            spawn_projectile(&mut commands, &weapon, origin, direction);
+        }
+        weapon.cooldown.reset();
+    }
+}
+
+pub fn fire_homing(
+    mut commands: Commands,
+    mut weapon_query: Query<(&GlobalTransform, &mut Weapon)>,
+    target_query: Query<&Transform, With<NearestMob>>,
+) {
+    let Ok(target) = target_query.get_single() else {
+        return;
+    };
+    for (transform, mut weapon) in &mut weapon_query {
+        if weapon.kind != WeaponKind::Homing || !weapon.cooldown.finished() {
+            continue;
+        }
+
+        let origin = transform.translation().xy();
+        let direction = (target.translation.xy() - origin).normalize_or_zero();
+        if direction == Vec2::ZERO {
+            continue;
+        }
+
+        for direction in fan(direction, weapon.stats.count, HOMING_SPREAD) {
+            let missile = spawn_projectile(&mut commands, &weapon, origin, direction);
+            commands.entity(missile).insert(Homing {
+                turn_rate: weapon.stats.turn_rate,
+            });
+        }
+        weapon.cooldown.reset();
+    }
+}
+
+pub fn steer_homing(
+    time: Res<Time>,
+    grid: Res<SpatialGrid<Mob>>,
+    mut missile_query: Query<(&mut Transform, &mut Velocity, &Homing)>,
+) {
+    for (mut transform, mut velocity, homing) in &mut missile_query {
+        let position = transform.translation.xy();
+        if let Some((_, target)) = grid.nearest(position, f32::INFINITY) {
+            let desired = target - position;
+            let turn = velocity.linvel.angle_between(desired);
+            if turn.is_finite() {
+                let max_turn = homing.turn_rate * time.delta_seconds();
+                velocity.linvel =
+                    Vec2::from_angle(turn.clamp(-max_turn, max_turn)).rotate(velocity.linvel);
+            }
+        }
+
+        transform.rotation = Quat::from_rotation_z(velocity.linvel.y.atan2(velocity.linvel.x));
+    }
+}
+
+pub fn fire_lasers(
+    mut commands: Commands,
+    mut weapon_query: Query<(&GlobalTransform, &mut Weapon)>,
+    target_query: Query<&Transform, With<NearestMob>>,
+) {
+    let Ok(target) = target_query.get_single() else {
+        return;
+    };
+    for (transform, mut weapon) in &mut weapon_query {
+        if weapon.kind != WeaponKind::Laser || !weapon.cooldown.finished() {
+            continue;
+        }
+
+        let origin = transform.translation().xy();
+        let direction = (target.translation.xy() - origin).normalize_or_zero();
+        if direction == Vec2::ZERO {
+            continue;
+        }
+
+        let length = weapon.stats.area;
+        let width = weapon.stats.size;
+        for direction in fan(direction, weapon.stats.count, PROJECTILE_SPREAD) {
+            let centre = origin + direction * length / 2.;
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: weapon.colour,
+                        custom_size: Some(Vec2::new(length, width)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(centre.extend(0.5))
+                        .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x))),
+                    ..default()
+                },
+                Name::new("Laser"),
+                RigidBody::KinematicPositionBased,
+                Collider::cuboid(length / 2., width / 2.),
+                Sensor,
+                ActiveEvents::COLLISION_EVENTS,
+                Projectile {
+                    damage: weapon.stats.damage,
+                    knockback: weapon.stats.knockback,
+                    effect: weapon.stats.effect,
+                    hits_remaining: u32::MAX,
+                },
+                Lifetime(Timer::from_seconds(weapon.stats.duration, TimerMode::Once)),
+                RunEntity,
+            ));
+        }
+        weapon.cooldown.reset();
+    }
+}
+
+pub fn spawn_orbiters(
+    mut commands: Commands,
+    mut weapon_query: Query<(Entity, &GlobalTransform, &mut Weapon)>,
+) {
+    for (entity, transform, mut weapon) in &mut weapon_query {
+        if weapon.kind != WeaponKind::Orbit || !weapon.cooldown.finished() {
+            continue;
+        }
+
+        let count = weapon.stats.count.max(1);
+        for i in 0..count {
+            let angle = TAU * i as f32 / count as f32;
+            let position =
+                transform.translation().xy() + Vec2::from_angle(angle) * weapon.stats.area;
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: weapon.colour,
+                        custom_size: Some(Vec2::splat(weapon.stats.size)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(position.extend(0.5)),
+                    ..default()
+                },
+                Name::new("Orbiter"),
+                RigidBody::KinematicPositionBased,
+                Collider::ball(weapon.stats.size / 2.),
+                Sensor,
+                ActiveEvents::COLLISION_EVENTS,
+                Projectile {
+                    damage: weapon.stats.damage,
+                    knockback: weapon.stats.knockback,
+                    effect: weapon.stats.effect,
+                    hits_remaining: u32::MAX,
+                },
+                Orbiter {
+                    owner: entity,
+                    angle,
+                    radius: weapon.stats.area,
+                    angular_speed: weapon.stats.speed,
+                },
+                Lifetime(Timer::from_seconds(weapon.stats.duration, TimerMode::Once)),
+                RunEntity,
+            ));
+        }
+        weapon.cooldown.reset();
+    }
+}
+
+pub fn move_orbiters(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut orbiter_query: Query<(Entity, &mut Transform, &mut Orbiter)>,
+    owner_query: Query<&GlobalTransform>,
+) {
+    for (entity, mut transform, mut orbiter) in &mut orbiter_query {
+        let Ok(owner) = owner_query.get(orbiter.owner) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        orbiter.angle = (orbiter.angle + orbiter.angular_speed * time.delta_seconds()) % TAU;
+        let position = owner.translation().xy() + Vec2::from_angle(orbiter.angle) * orbiter.radius;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        transform.rotation = Quat::from_rotation_z(orbiter.angle);
+    }
+}
+
+pub fn pulse_auras(
+    mut damage_events: EventWriter<DamageEvent>,
+    mut status_events: EventWriter<ApplyStatus>,
+    grid: Res<SpatialGrid<Mob>>,
+    mut weapon_query: Query<(&GlobalTransform, &mut Weapon)>,
+) {
+    for (transform, mut weapon) in &mut weapon_query {
+        if weapon.kind != WeaponKind::Aura || !weapon.cooldown.finished() {
+            continue;
+        }
+
+        let origin = transform.translation().xy();
+        for (entity, position) in grid.within_radius(origin, weapon.stats.area) {
+            damage_events.send(DamageEvent {
+                target: entity,
+                amount: weapon.stats.damage,
+                knockback: (position - origin).normalize_or_zero() * weapon.stats.knockback,
+                over_time: false,
+            });
+            if let Some(effect) = weapon.stats.effect {
+                status_events.send(ApplyStatus {
+                    target: entity,
+                    effect,
+                });
+            }
+        }
+        weapon.cooldown.reset();
+    }
+}
+
+pub fn spawn_aura_visuals(
+    mut commands: Commands,
+    handles: Res<SpriteAssets>,
+    weapon_query: Query<(Entity, &Weapon), Added<Weapon>>,
+) {
+    for (entity, weapon) in weapon_query.iter() {
+        if weapon.kind != WeaponKind::Aura {
+            continue;
+        }
+
+        let aura = commands
+            .spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: weapon.colour.with_a(AURA_OPACITY),
+                        custom_size: Some(Vec2::splat(weapon.stats.area * 2.)),
+                        ..default()
+                    },
+                    texture: handles.circle.clone(),
+                    transform: Transform::from_translation(Vec3::new(0., 0., -0.5)),
+                    ..default()
+                },
+                Name::new("AuraVisual"),
+                AuraVisual,
+            ))
+            .id();
+        commands.entity(entity).add_child(aura);
+    }
+}
+
+pub fn update_aura_visuals(
+    weapon_query: Query<(&Weapon, &Children)>,
+    mut aura_query: Query<&mut Sprite, With<AuraVisual>>,
+) {
+    for (weapon, children) in weapon_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut sprite) = aura_query.get_mut(*child) {
+                sprite.custom_size = Some(Vec2::splat(weapon.stats.area * 2.));
+            }
+        }
+    }
+}
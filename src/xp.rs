@@ -0,0 +1,179 @@
+use crate::prelude::*;
+
+const GEM_SIZE: f32 = 14.0;
+const GEM_COLOUR: Color = Color::CYAN;
+const GEM_SPEED: f32 = 700.0;
+const PICKUP_DISTANCE: f32 = 24.0;
+
+#[derive(Component)]
+pub struct XpGem {
+    pub value: u32,
+}
+
+/// Experience a mob drops when it dies
+#[derive(Component)]
+pub struct XpDrop(pub u32);
+
+/// Pulls experience gems within `radius` towards the entity
+#[derive(Component)]
+pub struct Magnet {
+    pub radius: f32,
+}
+
+/// Gems caught by a magnet keep flying towards the player even if it moves away
+#[derive(Component)]
+pub struct Attracted;
+
+#[derive(Component)]
+pub struct Experience {
+    pub level: u32,
+    pub current: u32,
+    /// Experience needed to reach the next level
+    pub next: u32,
+    /// Levels gained that still need an upgrade to be chosen
+    pub pending_level_ups: u32,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Self {
+            level: 1,
+            current: 0,
+            next: experience_for_level(1),
+            pending_level_ups: 0,
+        }
+    }
+}
+
+impl Experience {
+    pub fn add(&mut self, amount: u32) {
+        self.current += amount;
+        while self.current >= self.next {
+            self.current -= self.next;
+            self.level += 1;
+            self.next = experience_for_level(self.level);
+            self.pending_level_ups += 1;
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.current as f32 / self.next as f32
+    }
+}
+
+/// Experience needed to advance from `level` to the one after it
+pub fn experience_for_level(level: u32) -> u32 {
+    5 + level.saturating_sub(1) * 10
+}
+
+pub struct XpPlugin;
+
+impl Plugin for XpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(SpatialGridPlugin::<XpGem>::new(DEFAULT_CELL_SIZE))
+            .add_systems(
+                FixedUpdate,
+                (
+                    drop_xp_gems.after(apply_damage).before(despawn_dead_mobs),
+                    attract_gems,
+                    collect_gems,
+                    start_level_up.after(player_death),
+                )
+                    .chain()
+                    .in_set(TickSet::Gameplay)
+                    .run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+pub fn drop_xp_gems(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    handles: Res<SpriteAssets>,
+    mob_query: Query<(&Transform, &XpDrop), With<Mob>>,
+) {
+    for event in death_events.read() {
+        if let Ok((transform, drop)) = mob_query.get(event.entity) {
+            if drop.0 == 0 {
+                continue;
+            }
+
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: GEM_COLOUR,
+                        custom_size: Some(Vec2::splat(GEM_SIZE)),
+                        ..default()
+                    },
+                    texture: handles.circle.clone(),
+                    transform: Transform::from_translation(transform.translation.xy().extend(-0.5)),
+                    ..default()
+                },
+                Name::new("XpGem"),
+                XpGem { value: drop.0 },
+                RunEntity,
+            ));
+        }
+    }
+}
+
+pub fn attract_gems(
+    mut commands: Commands,
+    time: Res<Time>,
+    grid: Res<SpatialGrid<XpGem>>,
+    player_query: Query<(&Transform, &Magnet), With<Player>>,
+    mut gem_query: Query<(&mut Transform, Has<Attracted>), (With<XpGem>, Without<Player>)>,
+) {
+    if let Ok((player, magnet)) = player_query.get_single() {
+        let target = player.translation.xy();
+        for (entity, _) in grid.within_radius(target, magnet.radius) {
+            if let Ok((_, false)) = gem_query.get(entity) {
+                commands.entity(entity).insert(Attracted);
+            }
+        }
+
+        for (mut transform, attracted) in &mut gem_query {
+            if !attracted {
+                continue;
+            }
+
+            let offset = target - transform.translation.xy();
+            let step = offset.clamp_length_max(GEM_SPEED * time.delta_seconds());
+            transform.translation.x += step.x;
+            transform.translation.y += step.y;
+        }
+    }
+}
+
+/// Only attracted gems are checked, since the magnet always reaches further than pickup
+pub fn collect_gems(
+    mut commands: Commands,
+    mut player_query: Query<(&Transform, &mut Experience), With<Player>>,
+    gem_query: Query<(Entity, &Transform, &XpGem), With<Attracted>>,
+) {
+    if let Ok((player, mut experience)) = player_query.get_single_mut() {
+        for (entity, transform, gem) in gem_query.iter() {
+            let distance_sq = player
+                .translation
+                .xy()
+                .distance_squared(transform.translation.xy());
+            if distance_sq <= PICKUP_DISTANCE.powf(2.) {
+                experience.add(gem.value);
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+/// Opens the level-up screen for pending level ups, but never over a dead player's game over
+pub fn start_level_up(
+    player_query: Query<(&Experience, &Health), With<Player>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Ok((experience, health)) = player_query.get_single() else {
+        return;
+    };
+    if experience.pending_level_ups > 0 && !health.is_dead() {
+        next_state.set(AppState::LevelUp);
+    }
+}